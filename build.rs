@@ -0,0 +1,47 @@
+use std::path::Path;
+use std::process::Command;
+
+fn main() {
+    // Allow the SHA to be injected when building outside of a git checkout
+    println!("cargo:rerun-if-env-changed=GIT_SHA");
+    watch_git_head(Path::new(".git"));
+
+    let git_sha = std::env::var("GIT_SHA").ok().or_else(|| {
+        Command::new("git")
+            .args(["rev-parse", "HEAD"])
+            .output()
+            .ok()
+            .filter(|output| output.status.success())
+            .and_then(|output| String::from_utf8(output.stdout).ok())
+            .map(|sha| sha.trim().to_string())
+    });
+    println!(
+        "cargo:rustc-env=GIT_SHA={}",
+        git_sha.unwrap_or_else(|| "unknown".into())
+    );
+    println!(
+        "cargo:rustc-env=BUILD_TIMESTAMP={}",
+        chrono::Utc::now().to_rfc3339()
+    );
+}
+
+// HEAD only changes on a branch switch, a commit moves the ref it points to,
+// which lives either in its own file or in packed-refs
+fn watch_git_head(git_dir: &Path) {
+    let head = git_dir.join("HEAD");
+    let Ok(contents) = std::fs::read_to_string(&head) else {
+        return;
+    };
+    println!("cargo:rerun-if-changed={}", head.display());
+    if let Some(reference) = contents.trim().strip_prefix("ref: ") {
+        let reference = git_dir.join(reference);
+        // A ref that only exists in packed-refs has no file to watch
+        if reference.exists() {
+            println!("cargo:rerun-if-changed={}", reference.display());
+        }
+    }
+    let packed_refs = git_dir.join("packed-refs");
+    if packed_refs.exists() {
+        println!("cargo:rerun-if-changed={}", packed_refs.display());
+    }
+}
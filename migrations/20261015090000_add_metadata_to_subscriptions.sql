@@ -0,0 +1,2 @@
+-- Add metadata to Subscriptions for extra form fields
+ALTER TABLE subscriptions ADD COLUMN metadata jsonb NOT NULL DEFAULT '{}';
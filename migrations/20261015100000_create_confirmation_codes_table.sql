@@ -0,0 +1,8 @@
+-- Create confirmation_codes table
+CREATE TABLE confirmation_codes(
+  subscriber_id uuid NOT NULL REFERENCES subscriptions (id),
+  code_hash TEXT NOT NULL,
+  expires_at timestamptz NOT NULL,
+  failed_attempts INT NOT NULL DEFAULT 0,
+  PRIMARY KEY (subscriber_id)
+);
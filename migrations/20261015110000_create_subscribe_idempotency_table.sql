@@ -0,0 +1,7 @@
+-- Create subscribe_idempotency table
+CREATE TABLE subscribe_idempotency(
+  idempotency_key uuid NOT NULL,
+  response_status_code SMALLINT NOT NULL,
+  created_at timestamptz NOT NULL,
+  PRIMARY KEY (idempotency_key)
+);
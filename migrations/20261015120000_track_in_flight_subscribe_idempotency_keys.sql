@@ -0,0 +1,4 @@
+-- A row without a status code marks a request that is still being processed
+ALTER TABLE subscribe_idempotency ALTER COLUMN response_status_code DROP NOT NULL;
+-- Expired keys are deleted by age
+CREATE INDEX subscribe_idempotency_created_at_idx ON subscribe_idempotency (created_at);
@@ -0,0 +1,3 @@
+-- A key replays its status only for the same form, existing keys get a hash no form matches
+ALTER TABLE subscribe_idempotency ADD COLUMN request_hash TEXT NOT NULL DEFAULT '';
+ALTER TABLE subscribe_idempotency ALTER COLUMN request_hash DROP DEFAULT;
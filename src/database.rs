@@ -0,0 +1,133 @@
+use std::future::Future;
+use std::time::Duration;
+
+// serialization_failure and deadlock_detected: the transaction was rolled
+// back and can be run again as-is
+const RETRYABLE_SQLSTATES: &[&str] = &["40001", "40P01"];
+
+const BASE_BACKOFF: Duration = Duration::from_millis(10);
+
+pub fn is_transient(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => {
+            matches!(e.code(), Some(code) if RETRYABLE_SQLSTATES.contains(&code.as_ref()))
+        }
+        // The connection was reset, another one from the pool may work
+        sqlx::Error::Io(_) => true,
+        _ => false,
+    }
+}
+
+/// Run an idempotent database operation, retrying it up to `retries` times
+/// with a short linear backoff while it fails with a transient error.
+pub async fn retry_transient<T, F, Fut>(retries: u32, mut operation: F) -> Result<T, sqlx::Error>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<T, sqlx::Error>>,
+{
+    let mut attempt = 0;
+    loop {
+        match operation().await {
+            Err(e) if is_transient(&e) && attempt < retries => {
+                attempt += 1;
+                tracing::warn!(
+                    error.message = %e,
+                    attempt,
+                    "Transient database error, retrying"
+                );
+                actix_web::rt::time::sleep(BASE_BACKOFF * attempt).await;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::database::retry_transient;
+    use claims::{assert_err, assert_ok_eq};
+    use sqlx::error::DatabaseError;
+    use std::borrow::Cow;
+    use std::cell::Cell;
+
+    #[derive(Debug)]
+    struct FakeDatabaseError(&'static str);
+
+    impl std::fmt::Display for FakeDatabaseError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            write!(f, "database error {}", self.0)
+        }
+    }
+
+    impl std::error::Error for FakeDatabaseError {}
+
+    impl DatabaseError for FakeDatabaseError {
+        fn message(&self) -> &str {
+            "fake database error"
+        }
+
+        fn code(&self) -> Option<Cow<'_, str>> {
+            Some(Cow::Borrowed(self.0))
+        }
+
+        fn as_error(&self) -> &(dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn as_error_mut(&mut self) -> &mut (dyn std::error::Error + Send + Sync + 'static) {
+            self
+        }
+
+        fn into_error(self: Box<Self>) -> Box<dyn std::error::Error + Send + Sync + 'static> {
+            self
+        }
+    }
+
+    fn database_error(code: &'static str) -> sqlx::Error {
+        sqlx::Error::Database(Box::new(FakeDatabaseError(code)))
+    }
+
+    #[tokio::test]
+    async fn a_serialization_failure_is_retried() {
+        let calls = Cell::new(0);
+        let outcome = retry_transient(2, || {
+            calls.set(calls.get() + 1);
+            let result = if calls.get() == 1 {
+                Err(database_error("40001"))
+            } else {
+                Ok(42)
+            };
+            async move { result }
+        })
+        .await;
+
+        assert_ok_eq!(outcome, 42);
+        assert_eq!(calls.get(), 2);
+    }
+
+    #[tokio::test]
+    async fn retries_stop_at_the_configured_count() {
+        let calls = Cell::new(0);
+        let outcome: Result<(), _> = retry_transient(2, || {
+            calls.set(calls.get() + 1);
+            async { Err(database_error("40001")) }
+        })
+        .await;
+
+        assert_err!(outcome);
+        assert_eq!(calls.get(), 3);
+    }
+
+    #[tokio::test]
+    async fn other_errors_are_not_retried() {
+        let calls = Cell::new(0);
+        let outcome: Result<(), _> = retry_transient(2, || {
+            calls.set(calls.get() + 1);
+            async { Err(database_error("23505")) }
+        })
+        .await;
+
+        assert_err!(outcome);
+        assert_eq!(calls.get(), 1);
+    }
+}
@@ -0,0 +1,68 @@
+use url::Url;
+
+#[derive(Debug)]
+pub struct ConfirmationLink(Url);
+
+impl ConfirmationLink {
+    pub fn new(base_url: &str, subscription_token: &str) -> Result<Self, url::ParseError> {
+        // Join with exactly one slash, whether or not the base URL ends with one
+        let base_url = base_url.trim_end_matches('/');
+        let mut link = Url::parse(&format!("{}/subscriptions/confirm", base_url))?;
+        link.query_pairs_mut()
+            .append_pair("subscription_token", subscription_token);
+        Ok(Self(link))
+    }
+}
+
+impl AsRef<str> for ConfirmationLink {
+    fn as_ref(&self) -> &str {
+        self.0.as_str()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::ConfirmationLink;
+    use claims::assert_err;
+
+    #[test]
+    fn a_base_url_without_trailing_slash_is_joined_with_one_slash() {
+        let link = ConfirmationLink::new("http://127.0.0.1", "token").unwrap();
+        assert_eq!(
+            link.as_ref(),
+            "http://127.0.0.1/subscriptions/confirm?subscription_token=token"
+        );
+    }
+
+    #[test]
+    fn a_base_url_with_trailing_slash_is_joined_with_one_slash() {
+        let link = ConfirmationLink::new("http://127.0.0.1/", "token").unwrap();
+        assert_eq!(
+            link.as_ref(),
+            "http://127.0.0.1/subscriptions/confirm?subscription_token=token"
+        );
+    }
+
+    #[test]
+    fn a_base_url_path_is_preserved() {
+        let link = ConfirmationLink::new("https://example.com/newsletter/", "token").unwrap();
+        assert_eq!(
+            link.as_ref(),
+            "https://example.com/newsletter/subscriptions/confirm?subscription_token=token"
+        );
+    }
+
+    #[test]
+    fn special_characters_in_the_token_are_percent_encoded() {
+        let link = ConfirmationLink::new("http://127.0.0.1", "a b&c=d/+").unwrap();
+        assert_eq!(
+            link.as_ref(),
+            "http://127.0.0.1/subscriptions/confirm?subscription_token=a+b%26c%3Dd%2F%2B"
+        );
+    }
+
+    #[test]
+    fn an_invalid_base_url_is_rejected() {
+        assert_err!(ConfirmationLink::new("not a url", "token"));
+    }
+}
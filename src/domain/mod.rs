@@ -1,7 +1,11 @@
+mod confirmation_link;
 mod new_subscriber;
 mod subscriber_email;
 mod subscriber_name;
+mod subscription_token;
 
+pub use confirmation_link::ConfirmationLink;
 pub use new_subscriber::NewSubscriber;
-pub use subscriber_email::SubscriberEmail;
+pub use subscriber_email::{EmailValidationError, SubscriberEmail};
 pub use subscriber_name::SubscriberName;
+pub use subscription_token::SubscriptionToken;
@@ -1,15 +1,79 @@
 use validator::validate_email;
 
-#[derive(Debug)]
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
 pub struct SubscriberEmail(String);
 
+// RFC 5321 caps a forward path at 256 octets, including the angle brackets
+const MAX_EMAIL_LENGTH: usize = 254;
+
+// Throwaway inbox providers, subscribing them only inflates the list
+const DISPOSABLE_DOMAINS: &[&str] = &[
+    "10minutemail.com",
+    "guerrillamail.com",
+    "mailinator.com",
+    "tempmail.com",
+    "trashmail.com",
+    "yopmail.com",
+];
+
+#[derive(thiserror::Error, Debug, Clone, Copy, PartialEq, Eq)]
+pub enum EmailValidationError {
+    #[error("The email address is empty")]
+    Empty,
+    #[error("The email address is longer than {MAX_EMAIL_LENGTH} characters")]
+    TooLong,
+    #[error("The email address has no @")]
+    MissingAt,
+    #[error("The part before the @ is not valid")]
+    InvalidLocalPart,
+    #[error("The part after the @ is not a valid domain")]
+    InvalidDomain,
+    #[error("Disposable email addresses are not accepted")]
+    Disposable,
+}
+
 impl SubscriberEmail {
     pub fn parse(s: String) -> Result<Self, String> {
-        if validate_email(&s) {
-            Ok(Self(s))
-        } else {
-            Err(format!("{} is not a valid email address", s))
+        Self::parse_detailed(s.clone()).map_err(|_| format!("{} is not a valid email address", s))
+    }
+
+    // Same rules as `parse`, with a machine-readable reason on failure
+    pub fn parse_detailed(s: String) -> Result<Self, EmailValidationError> {
+        if s.trim().is_empty() {
+            return Err(EmailValidationError::Empty);
+        }
+        if s.chars().count() > MAX_EMAIL_LENGTH {
+            return Err(EmailValidationError::TooLong);
+        }
+        let (local_part, domain) = s.rsplit_once('@').ok_or(EmailValidationError::MissingAt)?;
+        // Check each half against a known-good counterpart to tell them apart
+        if !validate_email(format!("{}@example.com", local_part).as_str()) {
+            return Err(EmailValidationError::InvalidLocalPart);
         }
+        if !validate_email(format!("user@{}", domain).as_str()) {
+            return Err(EmailValidationError::InvalidDomain);
+        }
+        if DISPOSABLE_DOMAINS.contains(&domain.to_lowercase().as_str()) {
+            return Err(EmailValidationError::Disposable);
+        }
+        Ok(Self(s))
+    }
+}
+
+impl TryFrom<String> for SubscriberEmail {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl TryFrom<&str> for SubscriberEmail {
+    type Error = String;
+
+    fn try_from(s: &str) -> Result<Self, Self::Error> {
+        Self::parse(s.to_string())
     }
 }
 
@@ -21,8 +85,8 @@ impl AsRef<str> for SubscriberEmail {
 
 #[cfg(test)]
 mod tests {
-    use crate::domain::SubscriberEmail;
-    use claims::assert_err;
+    use crate::domain::{EmailValidationError, SubscriberEmail};
+    use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::Fake;
 
@@ -54,6 +118,48 @@ mod tests {
         assert_err!(SubscriberEmail::parse(email));
     }
 
+    #[test]
+    fn try_from_str_delegates_to_parse() {
+        assert_ok!(SubscriberEmail::try_from("ursula@domain.com"));
+        assert_err!(SubscriberEmail::try_from("ursuladomain.com"));
+    }
+
+    #[test]
+    fn deserialization_rejects_an_invalid_email() {
+        let outcome: Result<SubscriberEmail, _> = serde_json::from_str(r#""ursuladomain.com""#);
+        assert_err!(outcome);
+    }
+
+    #[test]
+    fn parse_detailed_reports_why_an_email_is_rejected() {
+        let cases = [
+            ("", EmailValidationError::Empty),
+            ("   ", EmailValidationError::Empty),
+            (
+                &format!("{}@test.com", "a".repeat(250)),
+                EmailValidationError::TooLong,
+            ),
+            ("something.com", EmailValidationError::MissingAt),
+            ("@test.com", EmailValidationError::InvalidLocalPart),
+            ("ursula@", EmailValidationError::InvalidDomain),
+            ("ursula@-domain.com", EmailValidationError::InvalidDomain),
+            ("ursula@Mailinator.com", EmailValidationError::Disposable),
+        ];
+        for (email, reason) in cases {
+            assert_eq!(
+                SubscriberEmail::parse_detailed(email.to_string()).unwrap_err(),
+                reason,
+                "unexpected reason for {:?}",
+                email
+            );
+        }
+    }
+
+    #[test]
+    fn parse_rejects_disposable_emails() {
+        assert_err!(SubscriberEmail::parse("ursula@yopmail.com".to_string()));
+    }
+
     #[quickcheck_macros::quickcheck]
     fn valid_emails_are_parsed_successfully(valid_email: ValidEmailFixture) -> bool {
         SubscriberEmail::parse(valid_email.0).is_ok()
@@ -0,0 +1,76 @@
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+
+const TOKEN_LENGTH: usize = 25;
+
+#[derive(Debug, Clone, serde::Deserialize)]
+#[serde(try_from = "String")]
+pub struct SubscriptionToken(String);
+
+impl SubscriptionToken {
+    pub fn generate() -> Self {
+        let mut rng = thread_rng();
+        let token = std::iter::repeat_with(|| rng.sample(Alphanumeric))
+            .map(char::from)
+            .take(TOKEN_LENGTH)
+            .collect();
+        Self(token)
+    }
+
+    pub fn parse(s: String) -> Result<Self, String> {
+        let is_valid = s.len() == TOKEN_LENGTH && s.chars().all(|c| c.is_ascii_alphanumeric());
+        if is_valid {
+            Ok(Self(s))
+        } else {
+            Err("The subscription token is malformed".into())
+        }
+    }
+}
+
+impl TryFrom<String> for SubscriptionToken {
+    type Error = String;
+
+    fn try_from(s: String) -> Result<Self, Self::Error> {
+        Self::parse(s)
+    }
+}
+
+impl AsRef<str> for SubscriptionToken {
+    fn as_ref(&self) -> &str {
+        &self.0
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::domain::SubscriptionToken;
+    use claims::{assert_err, assert_ok};
+
+    #[test]
+    fn generated_tokens_are_valid() {
+        for _ in 0..100 {
+            let token = SubscriptionToken::generate();
+            assert_ok!(SubscriptionToken::parse(token.as_ref().to_string()));
+        }
+    }
+
+    #[test]
+    fn a_25_character_alphanumeric_token_is_accepted() {
+        assert_ok!(SubscriptionToken::parse("abcdefghijklmnopqrstuvw12".into()));
+    }
+
+    #[test]
+    fn a_token_of_the_wrong_length_is_rejected() {
+        assert_err!(SubscriptionToken::parse("".into()));
+        assert_err!(SubscriptionToken::parse("abcdefghijklmnopqrstuvw1".into()));
+        assert_err!(SubscriptionToken::parse(
+            "abcdefghijklmnopqrstuvw123".into()
+        ));
+    }
+
+    #[test]
+    fn a_token_with_other_characters_is_rejected() {
+        assert_err!(SubscriptionToken::parse("abcdefghijklmnopqrstuvw1-".into()));
+        assert_err!(SubscriptionToken::parse("abcdefghijklmnopqrstuvwé".into()));
+    }
+}
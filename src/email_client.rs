@@ -1,13 +1,187 @@
 use crate::domain::SubscriberEmail;
-use reqwest::Client;
+use crate::email_content::{check_text_part, html_to_text};
+use reqwest::{Client, Proxy};
 use secrecy::{ExposeSecret, Secret};
+use std::borrow::Cow;
+use unicode_segmentation::UnicodeSegmentation;
+
+pub const DEFAULT_MAX_SUBJECT_LEN: usize = 255;
+pub const DEFAULT_MAX_BODY_BYTES: usize = 1024 * 1024;
+pub const DEFAULT_MAX_TEXT_PART_RATIO: f64 = 3.0;
+
+/// Which body parts are handed to the provider.
+#[derive(serde::Deserialize, Clone, Copy, Debug, Default, PartialEq, Eq)]
+#[serde(rename_all = "lowercase")]
+pub enum ContentFormat {
+    /// Both parts, plain text before HTML as multipart/alternative expects.
+    #[default]
+    Multipart,
+    Html,
+    Text,
+}
 
 pub struct EmailClient {
     http_client: Client,
+    timeout: std::time::Duration,
     base_url: String,
     sender: SubscriberEmail,
     api_token: Secret<String>,
     secret_token: Secret<String>,
+    max_subject_len: usize,
+    truncate_long_subjects: bool,
+    max_body_bytes: usize,
+    content_format: ContentFormat,
+    archive_bcc: Option<SubscriberEmail>,
+    override_recipient: Option<SubscriberEmail>,
+    max_text_part_ratio: f64,
+    generate_text_part: bool,
+}
+
+#[derive(thiserror::Error, Debug)]
+pub enum EmailClientError {
+    #[error("The email subject is {length} characters long, the maximum is {max}")]
+    SubjectTooLong { length: usize, max: usize },
+    #[error("The email body is {size} bytes, the maximum is {max}")]
+    BodyTooLarge { size: usize, max: usize },
+    #[error("The email provider did not respond in time")]
+    Timeout(#[source] reqwest::Error),
+    #[error("The email provider answered with {status}")]
+    Status {
+        status: reqwest::StatusCode,
+        #[source]
+        source: reqwest::Error,
+    },
+    #[error(transparent)]
+    Transport(reqwest::Error),
+}
+
+impl EmailClientError {
+    // The provider failed, was unreachable or throttled us; a 4xx rejection of
+    // the request itself would fail anywhere
+    pub fn is_provider_failure(&self) -> bool {
+        match self {
+            Self::Timeout(_) | Self::Transport(_) => true,
+            Self::Status { status, .. } => {
+                status.is_server_error() || *status == reqwest::StatusCode::TOO_MANY_REQUESTS
+            }
+            _ => false,
+        }
+    }
+}
+
+impl From<reqwest::Error> for EmailClientError {
+    // Timeouts are told apart so callers can retry them separately from other failures
+    fn from(e: reqwest::Error) -> Self {
+        if e.is_timeout() {
+            Self::Timeout(e)
+        } else if let Some(status) = e.status() {
+            Self::Status { status, source: e }
+        } else {
+            Self::Transport(e)
+        }
+    }
+}
+
+/// Anything that can deliver an email on our behalf.
+#[async_trait::async_trait]
+pub trait EmailProvider: Sync {
+    async fn send_email(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+    ) -> Result<(), EmailClientError> {
+        self.send_email_with_custom_id(recipient, subject, html_content, text_content, None)
+            .await
+    }
+
+    /// Passing the same `custom_id` on every attempt lets the provider
+    /// recognise a retry of a message it already accepted.
+    async fn send_email_with_custom_id(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        custom_id: Option<&str>,
+    ) -> Result<(), EmailClientError>;
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for EmailClient {
+    async fn send_email_with_custom_id(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        custom_id: Option<&str>,
+    ) -> Result<(), EmailClientError> {
+        EmailClient::send_email_with_custom_id(
+            self,
+            recipient,
+            subject,
+            html_content,
+            text_content,
+            custom_id,
+        )
+        .await
+    }
+}
+
+/// Sends through the primary provider, falling back to the secondary one
+/// when the primary fails or cannot be reached.
+pub struct FailoverEmailClient {
+    primary: EmailClient,
+    fallback: Option<EmailClient>,
+}
+
+impl FailoverEmailClient {
+    pub fn new(primary: EmailClient, fallback: Option<EmailClient>) -> Self {
+        Self { primary, fallback }
+    }
+}
+
+#[async_trait::async_trait]
+impl EmailProvider for FailoverEmailClient {
+    async fn send_email_with_custom_id(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        custom_id: Option<&str>,
+    ) -> Result<(), EmailClientError> {
+        let outcome = self
+            .primary
+            .send_email_with_custom_id(
+                recipient.clone(),
+                subject,
+                html_content,
+                text_content,
+                custom_id,
+            )
+            .await;
+        match (outcome, &self.fallback) {
+            (Err(e), Some(fallback)) if e.is_provider_failure() => {
+                tracing::warn!(
+                    error.message = %e,
+                    "The primary email provider failed, sending through the fallback"
+                );
+                fallback
+                    .send_email_with_custom_id(
+                        recipient,
+                        subject,
+                        html_content,
+                        text_content,
+                        custom_id,
+                    )
+                    .await
+            }
+            (outcome, _) => outcome,
+        }
+    }
 }
 
 #[derive(serde::Serialize)]
@@ -22,10 +196,16 @@ struct EmailInformation<'a> {
 struct SendEmailRequest<'a> {
     from: EmailInformation<'a>,
     to: Vec<EmailInformation<'a>>,
+    #[serde(skip_serializing_if = "Vec::is_empty")]
+    bcc: Vec<EmailInformation<'a>>,
     subject: &'a str,
-    #[serde(rename = "HTMLPart")]
-    html_part: &'a str,
-    text_part: &'a str,
+    // Declared text first so the serialized parts keep multipart/alternative order
+    #[serde(skip_serializing_if = "Option::is_none")]
+    text_part: Option<&'a str>,
+    #[serde(rename = "HTMLPart", skip_serializing_if = "Option::is_none")]
+    html_part: Option<&'a str>,
+    #[serde(rename = "CustomID", skip_serializing_if = "Option::is_none")]
+    custom_id: Option<&'a str>,
 }
 
 #[derive(serde::Serialize)]
@@ -45,11 +225,73 @@ impl EmailClient {
         let http_client = Client::builder().timeout(timeout).build().unwrap();
         Self {
             http_client,
+            timeout,
             base_url,
             sender,
             api_token,
             secret_token,
+            max_subject_len: DEFAULT_MAX_SUBJECT_LEN,
+            truncate_long_subjects: false,
+            max_body_bytes: DEFAULT_MAX_BODY_BYTES,
+            content_format: ContentFormat::default(),
+            archive_bcc: None,
+            override_recipient: None,
+            max_text_part_ratio: DEFAULT_MAX_TEXT_PART_RATIO,
+            generate_text_part: false,
+        }
+    }
+
+    // For networks where outbound calls must go through an HTTP proxy
+    pub fn with_proxy(mut self, proxy: Option<Proxy>) -> Self {
+        if let Some(proxy) = proxy {
+            self.http_client = Client::builder()
+                .timeout(self.timeout)
+                .proxy(proxy)
+                .build()
+                .unwrap();
         }
+        self
+    }
+
+    // Subjects over the limit are rejected, or truncated with an ellipsis if `truncate` is set
+    pub fn with_subject_limit(mut self, max_subject_len: usize, truncate: bool) -> Self {
+        self.max_subject_len = max_subject_len;
+        self.truncate_long_subjects = truncate;
+        self
+    }
+
+    pub fn with_max_body_bytes(mut self, max_body_bytes: usize) -> Self {
+        self.max_body_bytes = max_body_bytes;
+        self
+    }
+
+    pub fn with_content_format(mut self, content_format: ContentFormat) -> Self {
+        self.content_format = content_format;
+        self
+    }
+
+    // Every outgoing email is silently copied to this address, for record-keeping
+    pub fn with_archive_bcc(mut self, archive_bcc: Option<SubscriberEmail>) -> Self {
+        self.archive_bcc = archive_bcc;
+        self
+    }
+
+    // Advisory only: sends still go out, with a warning logged
+    pub fn with_max_text_part_ratio(mut self, max_text_part_ratio: f64) -> Self {
+        self.max_text_part_ratio = max_text_part_ratio;
+        self
+    }
+
+    // An empty text part is replaced with one rendered from the HTML part
+    pub fn with_generated_text_part(mut self, generate_text_part: bool) -> Self {
+        self.generate_text_part = generate_text_part;
+        self
+    }
+
+    // Sandbox mode: everything goes to this address, tagged with the real recipient
+    pub fn with_override_recipient(mut self, override_recipient: Option<SubscriberEmail>) -> Self {
+        self.override_recipient = override_recipient;
+        self
     }
 
     pub async fn send_email(
@@ -58,7 +300,66 @@ impl EmailClient {
         subject: &str,
         html_content: &str,
         text_content: &str,
-    ) -> Result<(), reqwest::Error> {
+    ) -> Result<(), EmailClientError> {
+        self.send_email_with_custom_id(recipient, subject, html_content, text_content, None)
+            .await
+    }
+
+    #[tracing::instrument(
+        name = "Sending an email through the provider",
+        skip(self, recipient, html_content, text_content),
+        fields(
+            provider_status = tracing::field::Empty,
+            latency_ms = tracing::field::Empty
+        )
+    )]
+    pub async fn send_email_with_custom_id(
+        &self,
+        recipient: SubscriberEmail,
+        subject: &str,
+        html_content: &str,
+        text_content: &str,
+        custom_id: Option<&str>,
+    ) -> Result<(), EmailClientError> {
+        // Limited before the sandbox prefix, so staging accepts and truncates
+        // exactly the subjects production does
+        let subject = self.limit_subject(subject)?;
+        let (recipient, subject) = match &self.override_recipient {
+            Some(override_recipient) => (
+                override_recipient.clone(),
+                Cow::Owned(format!("[To: {}] {}", recipient.as_ref(), subject)),
+            ),
+            None => (recipient, subject),
+        };
+        // An empty plain-text alternative hurts deliverability
+        let text_content = if self.generate_text_part && text_content.trim().is_empty() {
+            Cow::Owned(html_to_text(html_content))
+        } else {
+            Cow::Borrowed(text_content)
+        };
+        let (html_part, text_part) = match self.content_format {
+            ContentFormat::Multipart => (Some(html_content), Some(text_content.as_ref())),
+            ContentFormat::Html => (Some(html_content), None),
+            ContentFormat::Text => (None, Some(text_content.as_ref())),
+        };
+        // Refuse oversized bodies before serializing them into a request
+        let size = html_part.map_or(0, str::len) + text_part.map_or(0, str::len);
+        if size > self.max_body_bytes {
+            return Err(EmailClientError::BodyTooLarge {
+                size,
+                max: self.max_body_bytes,
+            });
+        }
+        if let (Some(html), Some(text)) = (html_part, text_part) {
+            if let Some(mismatch) = check_text_part(html, text, self.max_text_part_ratio) {
+                tracing::warn!(
+                    html_text_len = mismatch.html_text_len,
+                    text_len = mismatch.text_len,
+                    "The text part and the HTML part differ widely in length, \
+                    consider generating the text part from the HTML"
+                );
+            }
+        }
         let url = format!("{}/send", self.base_url);
         let request_body_inner = SendEmailRequest {
             from: EmailInformation {
@@ -69,14 +370,25 @@ impl EmailClient {
                 email: recipient.as_ref(),
                 name: None,
             }],
-            subject,
-            html_part: html_content,
-            text_part: text_content,
+            bcc: self
+                .archive_bcc
+                .iter()
+                .map(|email| EmailInformation {
+                    email: email.as_ref(),
+                    name: None,
+                })
+                .collect(),
+            subject: &subject,
+            text_part,
+            html_part,
+            custom_id,
         };
         let request_body = SendEmailRequestBody {
             messages: vec![request_body_inner],
         };
-        self.http_client
+        let start = std::time::Instant::now();
+        let outcome = self
+            .http_client
             .post(&url)
             .basic_auth(
                 self.api_token.expose_secret(),
@@ -84,16 +396,40 @@ impl EmailClient {
             )
             .json(&request_body)
             .send()
-            .await?
-            .error_for_status()?;
+            .await;
+        let span = tracing::Span::current();
+        span.record("latency_ms", start.elapsed().as_millis() as u64);
+        let response = outcome?;
+        span.record("provider_status", response.status().as_u16());
+        response.error_for_status()?;
         Ok(())
     }
+
+    fn limit_subject<'a>(&self, subject: &'a str) -> Result<Cow<'a, str>, EmailClientError> {
+        let length = subject.graphemes(true).count();
+        if length <= self.max_subject_len {
+            Ok(Cow::Borrowed(subject))
+        } else if self.truncate_long_subjects {
+            let truncated: String = subject
+                .graphemes(true)
+                .take(self.max_subject_len.saturating_sub(1))
+                .collect();
+            Ok(Cow::Owned(format!("{}…", truncated)))
+        } else {
+            Err(EmailClientError::SubjectTooLong {
+                length,
+                max: self.max_subject_len,
+            })
+        }
+    }
 }
 
 #[cfg(test)]
 mod tests {
     use crate::domain::SubscriberEmail;
-    use crate::email_client::EmailClient;
+    use crate::email_client::{
+        ContentFormat, EmailClient, EmailClientError, EmailProvider, FailoverEmailClient,
+    };
     use claims::{assert_err, assert_ok};
     use fake::faker::internet::en::SafeEmail;
     use fake::faker::lorem::en::{Paragraph, Sentence};
@@ -232,6 +568,405 @@ mod tests {
             .await;
 
         // Assert
-        assert_err!(outcome);
+        assert!(matches!(outcome, Err(EmailClientError::Timeout(_))));
+    }
+
+    #[tokio::test]
+    async fn send_email_rejects_a_subject_over_the_limit() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_subject_limit(10, false);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                email(),
+                "A subject that is too long",
+                &content(),
+                &content(),
+            )
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::SubjectTooLong { max: 10, .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_truncates_a_subject_over_the_limit_when_configured() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_subject_limit(10, true);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(
+                email(),
+                "A subject that is too long",
+                &content(),
+                &content(),
+            )
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Messages"][0]["Subject"], "A subject…");
+    }
+
+    #[tokio::test]
+    async fn send_email_rejects_an_oversized_body_without_sending() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_max_body_bytes(100);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let body = "a".repeat(60);
+        let outcome = email_client
+            .send_email(email(), &subject(), &body, &body)
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::BodyTooLarge {
+                size: 120,
+                max: 100
+            })
+        ));
+    }
+
+    #[tokio::test]
+    async fn send_email_puts_the_text_part_before_the_html_part() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), "<p>html</p>", "text")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let raw = std::str::from_utf8(&request.body).unwrap();
+        let text_at = raw.find(r#""TextPart":"text""#).unwrap();
+        let html_at = raw.find(r#""HTMLPart":"<p>html</p>""#).unwrap();
+        assert!(text_at < html_at);
+    }
+
+    #[tokio::test]
+    async fn send_email_only_sends_the_configured_part() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(2)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        for format in [ContentFormat::Html, ContentFormat::Text] {
+            let outcome = email_client(mock_server.uri())
+                .with_content_format(format)
+                .send_email(email(), &subject(), "<p>html</p>", "text")
+                .await;
+            assert_ok!(outcome);
+        }
+
+        // Assert
+        let requests = mock_server.received_requests().await.unwrap();
+        let html_only: serde_json::Value = serde_json::from_slice(&requests[0].body).unwrap();
+        assert_eq!(html_only["Messages"][0]["HTMLPart"], "<p>html</p>");
+        assert!(html_only["Messages"][0].get("TextPart").is_none());
+        let text_only: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+        assert_eq!(text_only["Messages"][0]["TextPart"], "text");
+        assert!(text_only["Messages"][0].get("HTMLPart").is_none());
+    }
+
+    #[tokio::test]
+    async fn send_email_bccs_the_archive_address_when_configured() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let archive = email();
+        let email_client = email_client(mock_server.uri()).with_archive_bcc(Some(archive.clone()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Messages"][0]["Bcc"][0]["Email"], archive.as_ref());
+    }
+
+    #[tokio::test]
+    async fn send_email_has_no_bcc_without_an_archive_address() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert!(body["Messages"][0].get("Bcc").is_none());
+    }
+
+    #[tokio::test]
+    async fn failover_sends_through_the_fallback_when_the_primary_returns_500() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = FailoverEmailClient::new(
+            email_client(primary_server.uri()),
+            Some(email_client(fallback_server.uri())),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(500))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+    }
+
+    #[tokio::test]
+    async fn failover_does_not_resend_rejected_content() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = FailoverEmailClient::new(
+            email_client(primary_server.uri()).with_max_body_bytes(1),
+            Some(email_client(fallback_server.uri())),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::BodyTooLarge { .. })
+        ));
+    }
+
+    #[tokio::test]
+    async fn failover_does_not_resend_a_request_the_primary_rejected() {
+        // Arrange
+        let primary_server = MockServer::start().await;
+        let fallback_server = MockServer::start().await;
+        let email_client = FailoverEmailClient::new(
+            email_client(primary_server.uri()),
+            Some(email_client(fallback_server.uri())),
+        );
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(400))
+            .expect(1)
+            .mount(&primary_server)
+            .await;
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&fallback_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), &content(), &content())
+            .await;
+
+        // Assert
+        assert!(matches!(
+            outcome,
+            Err(EmailClientError::Status { status, .. }) if status.as_u16() == 400
+        ));
+    }
+
+    #[tokio::test]
+    async fn only_server_errors_and_throttling_count_as_provider_failures() {
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri());
+
+        for (status, is_provider_failure) in [
+            (500, true),
+            (503, true),
+            (429, true),
+            (400, false),
+            (401, false),
+            (413, false),
+        ] {
+            mock_server.reset().await;
+            Mock::given(any())
+                .respond_with(ResponseTemplate::new(status))
+                .mount(&mock_server)
+                .await;
+
+            let outcome = email_client
+                .send_email(email(), &subject(), &content(), &content())
+                .await;
+
+            assert_eq!(
+                outcome.unwrap_err().is_provider_failure(),
+                is_provider_failure,
+                "status {}",
+                status
+            );
+        }
+    }
+
+    #[tokio::test]
+    async fn send_email_goes_to_the_override_recipient_when_configured() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let sandbox = email();
+        let recipient = email();
+        let email_client =
+            email_client(mock_server.uri()).with_override_recipient(Some(sandbox.clone()));
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(recipient.clone(), "Welcome!", &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Messages"][0]["To"][0]["Email"], sandbox.as_ref());
+        assert_eq!(
+            body["Messages"][0]["Subject"],
+            format!("[To: {}] Welcome!", recipient.as_ref())
+        );
+    }
+
+    #[tokio::test]
+    async fn the_subject_limit_ignores_the_override_prefix() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let recipient = email();
+        let email_client = email_client(mock_server.uri())
+            .with_override_recipient(Some(email()))
+            .with_subject_limit(10, false);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(recipient.clone(), "Welcome!", &content(), &content())
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(
+            body["Messages"][0]["Subject"],
+            format!("[To: {}] Welcome!", recipient.as_ref())
+        );
+    }
+
+    #[tokio::test]
+    async fn an_empty_text_part_is_generated_from_the_html_when_enabled() {
+        // Arrange
+        let mock_server = MockServer::start().await;
+        let email_client = email_client(mock_server.uri()).with_generated_text_part(true);
+
+        Mock::given(any())
+            .respond_with(ResponseTemplate::new(200))
+            .expect(1)
+            .mount(&mock_server)
+            .await;
+
+        // Act
+        let outcome = email_client
+            .send_email(email(), &subject(), "<p>Hello</p><p>there</p>", "")
+            .await;
+
+        // Assert
+        assert_ok!(outcome);
+        let request = &mock_server.received_requests().await.unwrap()[0];
+        let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+        assert_eq!(body["Messages"][0]["TextPart"], "Hello\nthere");
     }
 }
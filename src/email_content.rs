@@ -0,0 +1,169 @@
+// Tags are dropped and the common entities decoded, which is close enough to
+// what a reader sees to compare against a text part
+pub fn strip_html(html: &str) -> String {
+    let mut text = String::with_capacity(html.len());
+    let mut in_tag = false;
+    for c in html.chars() {
+        match c {
+            '<' => {
+                in_tag = true;
+                text.push(' ');
+            }
+            '>' if in_tag => in_tag = false,
+            _ if !in_tag => text.push(c),
+            _ => {}
+        }
+    }
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&amp;", "&");
+    text.split_whitespace().collect::<Vec<_>>().join(" ")
+}
+
+// Plain-text rendering of an HTML body, keeping line and paragraph breaks
+pub fn html_to_text(html: &str) -> String {
+    let mut html = html.to_string();
+    for line_break in [
+        "<br>", "<br/>", "<br />", "</p>", "</div>", "</h1>", "</h2>", "</li>",
+    ] {
+        html = html.replace(line_break, "\n");
+    }
+    html.lines()
+        .map(strip_html)
+        .filter(|line| !line.is_empty())
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+// Placeholder replaced with the recipient's name
+const NAME_PLACEHOLDER: &str = "{{name}}";
+
+// Fills in `{{name}}`, using the fallback for recipients without a usable name
+pub fn personalize_text(template: &str, name: Option<&str>, fallback: &str) -> String {
+    let name = name.map(str::trim).filter(|name| !name.is_empty());
+    template.replace(NAME_PLACEHOLDER, name.unwrap_or(fallback))
+}
+
+// Same as `personalize_text`, escaping the name so it cannot inject markup
+pub fn personalize_html(template: &str, name: Option<&str>, fallback: &str) -> String {
+    let name = name.map(str::trim).filter(|name| !name.is_empty());
+    template.replace(NAME_PLACEHOLDER, &escape_html(name.unwrap_or(fallback)))
+}
+
+fn escape_html(s: &str) -> String {
+    s.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+        .replace('\'', "&#39;")
+}
+
+/// The text part and the visible HTML text differ in length by more than the
+/// allowed ratio, which spam filters tend to penalise.
+#[derive(Debug, PartialEq, Eq)]
+pub struct TextPartMismatch {
+    pub html_text_len: usize,
+    pub text_len: usize,
+}
+
+pub fn check_text_part(html: &str, text: &str, max_ratio: f64) -> Option<TextPartMismatch> {
+    let html_text_len = strip_html(html).chars().count();
+    let text_len = text
+        .split_whitespace()
+        .collect::<Vec<_>>()
+        .join(" ")
+        .chars()
+        .count();
+    let (shorter, longer) = if html_text_len < text_len {
+        (html_text_len, text_len)
+    } else {
+        (text_len, html_text_len)
+    };
+    if longer as f64 > shorter.max(1) as f64 * max_ratio {
+        Some(TextPartMismatch {
+            html_text_len,
+            text_len,
+        })
+    } else {
+        None
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::email_content::{
+        check_text_part, html_to_text, personalize_html, personalize_text, strip_html,
+        TextPartMismatch,
+    };
+    use claims::{assert_none, assert_some_eq};
+
+    const HTML: &str = "<p>Welcome to our newsletter!</p>\
+        <p>Click <a href=\"https://newsletter.test\">here</a> to confirm &amp; start reading.</p>";
+
+    #[test]
+    fn tags_are_stripped_and_entities_decoded() {
+        assert_eq!(
+            strip_html(HTML),
+            "Welcome to our newsletter! Click here to confirm & start reading."
+        );
+    }
+
+    #[test]
+    fn a_matching_text_part_passes() {
+        let text = "Welcome to our newsletter!\nClick here to confirm & start reading.";
+        assert_none!(check_text_part(HTML, text, 3.0));
+    }
+
+    #[test]
+    fn a_nearly_empty_text_part_is_flagged() {
+        assert_some_eq!(
+            check_text_part(HTML, "Hi", 3.0),
+            TextPartMismatch {
+                html_text_len: 65,
+                text_len: 2
+            }
+        );
+    }
+
+    #[test]
+    fn html_is_rendered_as_text_line_by_line() {
+        assert_eq!(
+            html_to_text(HTML),
+            "Welcome to our newsletter!\nClick here to confirm & start reading."
+        );
+    }
+
+    #[test]
+    fn each_recipient_gets_their_own_name_or_the_fallback() {
+        let template = "Hi {{name}}, thanks for subscribing!";
+
+        assert_eq!(
+            personalize_text(template, Some("Ursula"), "there"),
+            "Hi Ursula, thanks for subscribing!"
+        );
+        assert_eq!(
+            personalize_text(template, Some("Le Guin"), "there"),
+            "Hi Le Guin, thanks for subscribing!"
+        );
+        assert_eq!(
+            personalize_text(template, None, "there"),
+            "Hi there, thanks for subscribing!"
+        );
+        assert_eq!(
+            personalize_text(template, Some("  "), "there"),
+            "Hi there, thanks for subscribing!"
+        );
+    }
+
+    #[test]
+    fn names_are_escaped_in_html() {
+        assert_eq!(
+            personalize_html("<p>Hi {{name}}</p>", Some("<b>Ursula</b>"), "there"),
+            "<p>Hi &lt;b&gt;Ursula&lt;/b&gt;</p>"
+        );
+    }
+}
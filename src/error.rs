@@ -0,0 +1,104 @@
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::http::StatusCode;
+use actix_web::{HttpResponse, ResponseError};
+
+// How long clients should wait before retrying when the service is saturated
+const RETRY_AFTER_SECONDS: u32 = 1;
+
+pub fn sqlx_error_status(e: &sqlx::Error) -> StatusCode {
+    match e {
+        // The pool is saturated, a transient condition rather than a bug
+        sqlx::Error::PoolTimedOut => StatusCode::SERVICE_UNAVAILABLE,
+        _ => StatusCode::INTERNAL_SERVER_ERROR,
+    }
+}
+
+// Look for a database error anywhere in the chain to pick the status code
+pub fn unexpected_error_status(e: &anyhow::Error) -> StatusCode {
+    e.chain()
+        .find_map(|cause| cause.downcast_ref::<sqlx::Error>())
+        .map(sqlx_error_status)
+        .unwrap_or(StatusCode::INTERNAL_SERVER_ERROR)
+}
+
+pub fn error_response(e: &impl ResponseError) -> HttpResponse {
+    let status = e.status_code();
+    let mut response = HttpResponse::build(status);
+    if status == StatusCode::SERVICE_UNAVAILABLE {
+        response.insert_header((RETRY_AFTER, RETRY_AFTER_SECONDS));
+    }
+    response
+        .insert_header(ContentType::plaintext())
+        .body(e.to_string())
+}
+
+// Debug output for error enums: the error itself followed by its source chain
+pub(crate) fn error_chain_fmt(
+    e: &impl std::error::Error,
+    f: &mut std::fmt::Formatter<'_>,
+) -> std::fmt::Result {
+    writeln!(f, "{}\n", e)?;
+    let mut current = e.source();
+    while let Some(cause) = current {
+        writeln!(f, "Caused by:\n\t{}", cause)?;
+        current = cause.source();
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::error::{error_chain_fmt, sqlx_error_status, unexpected_error_status};
+    use actix_web::http::StatusCode;
+    use anyhow::Context;
+
+    #[test]
+    fn a_pool_timeout_maps_to_503() {
+        assert_eq!(
+            sqlx_error_status(&sqlx::Error::PoolTimedOut),
+            StatusCode::SERVICE_UNAVAILABLE
+        );
+    }
+
+    #[test]
+    fn other_database_errors_map_to_500() {
+        assert_eq!(
+            sqlx_error_status(&sqlx::Error::RowNotFound),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[test]
+    fn a_pool_timeout_is_found_behind_context() {
+        let e = Err::<(), _>(sqlx::Error::PoolTimedOut)
+            .context("Failed to acquire a Postgres connection from the pool")
+            .unwrap_err();
+        assert_eq!(unexpected_error_status(&e), StatusCode::SERVICE_UNAVAILABLE);
+    }
+
+    #[test]
+    fn errors_without_a_database_cause_map_to_500() {
+        let e = anyhow::anyhow!("Failed to send a confirmation email");
+        assert_eq!(
+            unexpected_error_status(&e),
+            StatusCode::INTERNAL_SERVER_ERROR
+        );
+    }
+
+    #[derive(thiserror::Error)]
+    #[error("Failed to store the subscription token")]
+    struct OuterError(#[source] sqlx::Error);
+
+    impl std::fmt::Debug for OuterError {
+        fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+            error_chain_fmt(self, f)
+        }
+    }
+
+    #[test]
+    fn the_error_chain_includes_each_cause() {
+        let output = format!("{:?}", OuterError(sqlx::Error::RowNotFound));
+        assert!(output.starts_with("Failed to store the subscription token\n"));
+        assert!(output.contains(&format!("Caused by:\n\t{}", sqlx::Error::RowNotFound)));
+    }
+}
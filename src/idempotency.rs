@@ -0,0 +1,150 @@
+use actix_web::http::StatusCode;
+use actix_web::HttpResponse;
+use chrono::{Duration, Utc};
+use sqlx::PgPool;
+use uuid::Uuid;
+
+// Keys are only meant to catch double submissions, so a day is plenty
+const KEY_RETENTION_HOURS: i64 = 24;
+
+// A claim still without a status after this long belongs to a request that
+// died, e.g. with the process, and is taken over by the next one
+const ABANDONED_CLAIM_SECONDS: i64 = 60;
+
+// How long a repeated request waits for the first one to finish
+const WAIT_ATTEMPTS: u32 = 50;
+const WAIT_INTERVAL: std::time::Duration = std::time::Duration::from_millis(100);
+
+pub enum NextAction {
+    StartProcessing,
+    ReturnSavedResponse(HttpResponse),
+    // The first request with this key is still running after the wait
+    StillProcessing,
+    // The key was first used for a different form
+    KeyReused,
+}
+
+// Claims the key with a placeholder row. A request that finds the key already
+// claimed waits for the first one's status and replays it. Subscribe responses
+// carry no body, so the status code is all there is to replay.
+#[tracing::instrument(name = "Claim a subscribe idempotency key", skip(pool, request_hash))]
+pub async fn try_processing(
+    pool: &PgPool,
+    idempotency_key: Uuid,
+    request_hash: &str,
+) -> Result<NextAction, anyhow::Error> {
+    delete_expired_keys(pool).await?;
+    for _ in 0..WAIT_ATTEMPTS {
+        let claimed = sqlx::query!(
+            r#"
+            INSERT INTO subscribe_idempotency (idempotency_key, request_hash, created_at)
+            VALUES ($1, $2, $3)
+            ON CONFLICT DO NOTHING
+            "#,
+            idempotency_key,
+            request_hash,
+            Utc::now()
+        )
+        .execute(pool)
+        .await?
+        .rows_affected()
+            == 1;
+        if claimed {
+            return Ok(NextAction::StartProcessing);
+        }
+        let saved = sqlx::query!(
+            r#"
+            SELECT response_status_code, request_hash, created_at
+            FROM subscribe_idempotency
+            WHERE idempotency_key = $1
+            "#,
+            idempotency_key
+        )
+        .fetch_optional(pool)
+        .await?;
+        // No row means the first request failed and released the key, so the
+        // loop claims it on the next pass
+        if let Some(saved) = saved {
+            if saved.request_hash != request_hash {
+                return Ok(NextAction::KeyReused);
+            }
+            if let Some(status_code) = saved.response_status_code {
+                let status = StatusCode::from_u16(status_code.try_into()?)?;
+                return Ok(NextAction::ReturnSavedResponse(
+                    HttpResponse::build(status).finish(),
+                ));
+            }
+            if take_over_abandoned_claim(pool, idempotency_key).await? {
+                return Ok(NextAction::StartProcessing);
+            }
+        }
+        actix_web::rt::time::sleep(WAIT_INTERVAL).await;
+    }
+    Ok(NextAction::StillProcessing)
+}
+
+// Restarts the claim's clock, so only one of several waiting requests wins it
+async fn take_over_abandoned_claim(
+    pool: &PgPool,
+    idempotency_key: Uuid,
+) -> Result<bool, sqlx::Error> {
+    let now = Utc::now();
+    let result = sqlx::query!(
+        r#"
+        UPDATE subscribe_idempotency
+        SET created_at = $2
+        WHERE idempotency_key = $1
+            AND response_status_code IS NULL
+            AND created_at < $3
+        "#,
+        idempotency_key,
+        now,
+        now - Duration::seconds(ABANDONED_CLAIM_SECONDS)
+    )
+    .execute(pool)
+    .await?;
+    Ok(result.rows_affected() == 1)
+}
+
+#[tracing::instrument(name = "Save a subscribe response", skip(pool, response))]
+pub async fn save_response(
+    pool: &PgPool,
+    idempotency_key: Uuid,
+    response: &HttpResponse,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        UPDATE subscribe_idempotency
+        SET response_status_code = $2
+        WHERE idempotency_key = $1
+        "#,
+        idempotency_key,
+        response.status().as_u16() as i16
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+// Failures are not saved, so a retry after one processes the form again
+#[tracing::instrument(name = "Release a subscribe idempotency key", skip(pool))]
+pub async fn release_key(pool: &PgPool, idempotency_key: Uuid) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscribe_idempotency WHERE idempotency_key = $1",
+        idempotency_key
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(name = "Delete expired subscribe idempotency keys", skip(pool))]
+async fn delete_expired_keys(pool: &PgPool) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM subscribe_idempotency WHERE created_at < $1",
+        Utc::now() - Duration::hours(KEY_RETENTION_HOURS)
+    )
+    .execute(pool)
+    .await?;
+    Ok(())
+}
@@ -1,6 +1,12 @@
 pub mod configuration;
+pub mod database;
 pub mod domain;
 pub mod email_client;
+pub mod email_content;
+pub mod error;
+pub mod idempotency;
 pub mod routes;
+pub mod shutdown;
 pub mod startup;
 pub mod telemetry;
+pub mod token_cache;
@@ -1,6 +1,27 @@
-use actix_web::{HttpResponse, Responder};
+use crate::startup::StartedAt;
+use actix_web::http::header::ACCEPT;
+use actix_web::{web, HttpRequest, HttpResponse};
 
-#[allow(clippy::all)]
-pub async fn health_check() -> impl Responder {
-    HttpResponse::Ok().finish()
+#[derive(serde::Serialize)]
+struct HealthStatus {
+    status: &'static str,
+    uptime_seconds: u64,
+    version: &'static str,
+}
+
+// Plain requests keep getting an empty 200 so existing monitors are unaffected
+pub async fn health_check(request: HttpRequest, started_at: web::Data<StartedAt>) -> HttpResponse {
+    let accept = request
+        .headers()
+        .get(ACCEPT)
+        .and_then(|value| value.to_str().ok());
+    let wants_json = matches!(accept, Some(accept) if accept.contains("application/json"));
+    if !wants_json {
+        return HttpResponse::Ok().finish();
+    }
+    HttpResponse::Ok().json(HealthStatus {
+        status: "ok",
+        uptime_seconds: started_at.0.elapsed().as_secs(),
+        version: env!("CARGO_PKG_VERSION"),
+    })
 }
@@ -1,7 +1,15 @@
 mod health_check;
+mod subscription_form;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_confirm_code;
+mod version;
+mod webhooks;
 
 pub use health_check::*;
+pub use subscription_form::*;
 pub use subscriptions::*;
 pub use subscriptions_confirm::*;
+pub use subscriptions_confirm_code::*;
+pub use version::*;
+pub use webhooks::*;
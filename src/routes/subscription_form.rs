@@ -0,0 +1,129 @@
+use crate::startup::{ApplicationBaseUrl, CsrfProtection};
+use actix_web::cookie::{Cookie, SameSite};
+use actix_web::http::header::ContentType;
+use actix_web::{web, HttpRequest, HttpResponse};
+use rand::distributions::Alphanumeric;
+use rand::{thread_rng, Rng};
+use subtle::ConstantTimeEq;
+
+pub const CSRF_COOKIE: &str = "csrf_token";
+
+const CSRF_TOKEN_LENGTH: usize = 32;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum CsrfError {
+    #[error("The form is missing its CSRF token")]
+    Missing,
+    #[error("The CSRF token does not match")]
+    Mismatch,
+}
+
+// The signup form, with a fresh CSRF token in both a cookie and a hidden field
+pub async fn subscription_form(base_url: web::Data<ApplicationBaseUrl>) -> HttpResponse {
+    let token = generate_csrf_token();
+    let cookie = Cookie::build(CSRF_COOKIE, token.clone())
+        .path("/subscriptions")
+        .http_only(true)
+        .same_site(SameSite::Strict)
+        .secure(base_url.0.starts_with("https://"))
+        .finish();
+    HttpResponse::Ok()
+        .cookie(cookie)
+        .content_type(ContentType::html())
+        .body(format!(
+            r#"<!DOCTYPE html>
+<html lang="en">
+<head><meta http-equiv="content-type" content="text/html; charset=utf-8"><title>Subscribe</title></head>
+<body>
+<form action="/subscriptions" method="post">
+<label>Name <input type="text" name="name" required></label>
+<label>Email <input type="email" name="email" required></label>
+<input type="hidden" name="csrf_token" value="{}">
+<button type="submit">Subscribe</button>
+</form>
+</body>
+</html>"#,
+            token
+        ))
+}
+
+fn generate_csrf_token() -> String {
+    let mut rng = thread_rng();
+    std::iter::repeat_with(|| rng.sample(Alphanumeric))
+        .map(char::from)
+        .take(CSRF_TOKEN_LENGTH)
+        .collect()
+}
+
+// Only browsers carry ambient credentials a forged post could ride on, so
+// requests without browser fetch metadata, e.g. API clients, are exempt
+pub fn verify_csrf_token(
+    request: &HttpRequest,
+    protection: &CsrfProtection,
+    form_token: Option<&str>,
+) -> Result<(), CsrfError> {
+    if !protection.0 || !is_browser_request(request) {
+        return Ok(());
+    }
+    let cookie = request.cookie(CSRF_COOKIE).ok_or(CsrfError::Missing)?;
+    let form_token = form_token.ok_or(CsrfError::Missing)?;
+    if bool::from(cookie.value().as_bytes().ct_eq(form_token.as_bytes())) {
+        Ok(())
+    } else {
+        Err(CsrfError::Mismatch)
+    }
+}
+
+fn is_browser_request(request: &HttpRequest) -> bool {
+    let headers = request.headers();
+    headers.contains_key("Origin") || headers.contains_key("Sec-Fetch-Site")
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::{verify_csrf_token, CsrfError, CSRF_COOKIE};
+    use crate::startup::CsrfProtection;
+    use actix_web::cookie::Cookie;
+    use actix_web::test::TestRequest;
+    use claims::{assert_err_eq, assert_ok};
+
+    const ENABLED: CsrfProtection = CsrfProtection(true);
+
+    #[test]
+    fn a_browser_post_with_a_matching_token_is_accepted() {
+        let request = TestRequest::post()
+            .insert_header(("Origin", "https://newsletter.test"))
+            .cookie(Cookie::new(CSRF_COOKIE, "token"))
+            .to_http_request();
+
+        assert_ok!(verify_csrf_token(&request, &ENABLED, Some("token")));
+    }
+
+    #[test]
+    fn a_browser_post_without_the_cookie_is_rejected() {
+        let request = TestRequest::post()
+            .insert_header(("Sec-Fetch-Site", "cross-site"))
+            .to_http_request();
+
+        assert_err_eq!(
+            verify_csrf_token(&request, &ENABLED, Some("token")),
+            CsrfError::Missing
+        );
+    }
+
+    #[test]
+    fn requests_without_browser_headers_are_exempt() {
+        let request = TestRequest::post().to_http_request();
+
+        assert_ok!(verify_csrf_token(&request, &ENABLED, None));
+    }
+
+    #[test]
+    fn nothing_is_checked_when_protection_is_disabled() {
+        let request = TestRequest::post()
+            .insert_header(("Origin", "https://elsewhere.test"))
+            .to_http_request();
+
+        assert_ok!(verify_csrf_token(&request, &CsrfProtection(false), None));
+    }
+}
@@ -1,27 +1,84 @@
-use crate::domain::{NewSubscriber, SubscriberEmail, SubscriberName};
-use crate::email_client::EmailClient;
-use crate::startup::ApplicationBaseUrl;
-use actix_web::{web, HttpResponse, ResponseError};
+use crate::configuration::{ConfirmationCodeSettings, ConfirmationEmailSettings};
+use crate::database::retry_transient;
+use crate::domain::{
+    ConfirmationLink, NewSubscriber, SubscriberEmail, SubscriberName, SubscriptionToken,
+};
+use crate::email_client::{EmailClientError, EmailProvider, FailoverEmailClient};
+use crate::error::{error_chain_fmt, error_response, unexpected_error_status};
+use crate::idempotency::{release_key, save_response, try_processing, NextAction};
+use crate::routes::{
+    generate_confirmation_code, store_confirmation_code, verify_csrf_token, CsrfError,
+};
+use crate::startup::{
+    ApplicationBaseUrl, CsrfProtection, HideSubscriptionExistence, SubscribeAdmission,
+    SubscriberMetadataFields, TransientDbRetries,
+};
+use actix_web::error::{InternalError, UrlencodedError};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
 use anyhow::Context;
-use chrono::Utc;
-use rand::distributions::Alphanumeric;
-use rand::{thread_rng, Rng};
+use chrono::{DateTime, Utc};
 use reqwest::StatusCode;
-use sqlx::{PgPool, Postgres, Transaction};
+use sha2::{Digest, Sha256};
+use sqlx::{Acquire, PgPool, Postgres, Transaction};
+use std::collections::{BTreeMap, HashMap};
+use std::future::Future;
 use uuid::Uuid;
 
 #[derive(serde::Deserialize)]
 pub struct FormData {
     name: String,
-    email: String,
+    // Validated while deserializing the form
+    #[serde(deserialize_with = "deserialize_email")]
+    email: SubscriberEmail,
+    // Client-generated UUID, so a double-submitted form is only processed once
+    idempotency_key: Option<String>,
+    // Echoed from the form page, see verify_csrf_token
+    csrf_token: Option<String>,
+    // Embedding sites may send extra fields, only allowlisted ones are kept
+    #[serde(flatten)]
+    extra: HashMap<String, String>,
+}
+
+impl FormData {
+    // Ties an idempotency key to the submission it was first used for. The
+    // CSRF token changes with every page load, so it is left out.
+    fn fingerprint(&self) -> String {
+        let extra: BTreeMap<&String, &String> = self.extra.iter().collect();
+        let fields = serde_json::json!([self.name, self.email.as_ref(), extra]);
+        hex::encode(Sha256::digest(fields.to_string().as_bytes()))
+    }
+}
+
+// The rejected address never reaches the error, which ends up in logs
+const INVALID_EMAIL: &str = "Invalid subscription form fields: email";
+
+pub(crate) fn deserialize_email<'de, D>(deserializer: D) -> Result<SubscriberEmail, D::Error>
+where
+    D: serde::Deserializer<'de>,
+{
+    let email = <String as serde::Deserialize>::deserialize(deserializer)?;
+    SubscriberEmail::parse_detailed(email)
+        .map_err(|_| <D::Error as serde::de::Error>::custom(INVALID_EMAIL))
 }
 
 pub struct StoreTokenError(sqlx::Error);
 
+const MAX_TOKEN_ATTEMPTS: u32 = 3;
+
 #[derive(thiserror::Error)]
 pub enum SubscribeError {
     #[error("{0}")]
     ValidationError(String),
+    #[error("This email address is already subscribed")]
+    AlreadySubscribed,
+    #[error("Too many subscriptions are being processed, try again shortly")]
+    Overloaded,
+    #[error("A request with this idempotency key is still being processed")]
+    StillProcessing,
+    #[error("This idempotency key was already used for a different form")]
+    IdempotencyKeyReused,
+    #[error("The form could not be verified, reload the page and try again")]
+    InvalidCsrfToken(#[source] CsrfError),
     #[error(transparent)]
     UnexpectedError(#[from] anyhow::Error),
 }
@@ -36,9 +93,15 @@ impl TryFrom<FormData> for NewSubscriber {
     type Error = String;
 
     fn try_from(form: FormData) -> Result<Self, Self::Error> {
-        let name = SubscriberName::parse(form.name)?;
-        let email = SubscriberEmail::parse(form.email)?;
-        Ok(NewSubscriber { email, name })
+        // Only report field names, the rejected values may contain PII
+        let name = SubscriberName::parse(form.name).map_err(|_| {
+            tracing::debug!(rejected_fields = ?["name"], "Rejected invalid subscription form fields");
+            "Invalid subscription form fields: name".to_string()
+        })?;
+        Ok(NewSubscriber {
+            email: form.email,
+            name,
+        })
     }
 }
 
@@ -68,106 +131,415 @@ impl ResponseError for SubscribeError {
     fn status_code(&self) -> reqwest::StatusCode {
         match self {
             SubscribeError::ValidationError(_) => StatusCode::BAD_REQUEST,
-            SubscribeError::UnexpectedError(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SubscribeError::Overloaded => StatusCode::SERVICE_UNAVAILABLE,
+            SubscribeError::AlreadySubscribed => StatusCode::CONFLICT,
+            SubscribeError::StillProcessing => StatusCode::CONFLICT,
+            SubscribeError::IdempotencyKeyReused => StatusCode::UNPROCESSABLE_ENTITY,
+            SubscribeError::InvalidCsrfToken(_) => StatusCode::FORBIDDEN,
+            SubscribeError::UnexpectedError(e) => unexpected_error_status(e),
         }
     }
+
+    fn error_response(&self) -> HttpResponse {
+        error_response(self)
+    }
 }
 
 #[tracing::instrument(
     name = "Adding a new subscriber",
-    skip(form, pool, email_client, base_url),
-    fields(
-        subscriber_email = %form.email,
-        subscriber_name = %form.name
-    )
+    skip(
+        request,
+        form,
+        pool,
+        email_client,
+        base_url,
+        metadata_fields,
+        hide_existence,
+        code_settings,
+        email_settings,
+        admission,
+        csrf_protection,
+        retries
+    ),
+    fields(subscriber_name = %form.name)
 )]
+#[allow(clippy::too_many_arguments)]
 pub async fn subscribe(
+    request: HttpRequest,
     form: web::Form<FormData>,
     pool: web::Data<PgPool>,
-    email_client: web::Data<EmailClient>,
+    email_client: web::Data<FailoverEmailClient>,
     base_url: web::Data<ApplicationBaseUrl>,
+    metadata_fields: web::Data<SubscriberMetadataFields>,
+    hide_existence: web::Data<HideSubscriptionExistence>,
+    code_settings: web::Data<ConfirmationCodeSettings>,
+    email_settings: web::Data<ConfirmationEmailSettings>,
+    admission: web::Data<SubscribeAdmission>,
+    csrf_protection: web::Data<CsrfProtection>,
+    retries: web::Data<TransientDbRetries>,
 ) -> Result<HttpResponse, SubscribeError> {
-    let new_subscriber = form.0.try_into().map_err(SubscribeError::ValidationError)?;
-    let mut transaction = pool
-        .begin()
-        .await
-        .context("Failed to acquire a Postgres connection from the pool")?;
-    let subscriber_id = insert_subscriber(&mut transaction, &new_subscriber)
-        .await
-        .context("Failed to insert a new subscriber in the database")?;
-    let subscription_token = generate_subscription_token();
-    store_token(&mut transaction, subscriber_id, &subscription_token)
-        .await
-        .context("Failed to store the confirmation token for a new subscriber")?;
-    transaction
-        .commit()
-        .await
-        .context("Failed to commit SQL transaction to store a new subscriber")?;
-    send_confirmation_email(
-        &email_client,
-        new_subscriber,
+    verify_csrf_token(&request, &csrf_protection, form.csrf_token.as_deref())
+        .map_err(SubscribeError::InvalidCsrfToken)?;
+    // Shed load up front rather than queueing for a pool connection and timing out
+    let _permit = admission
+        .0
+        .try_acquire()
+        .map_err(|_| SubscribeError::Overloaded)?;
+    let idempotency_key = form
+        .idempotency_key
+        .as_deref()
+        .map(Uuid::parse_str)
+        .transpose()
+        .map_err(|_| SubscribeError::ValidationError("Invalid idempotency key".into()))?;
+    if let Some(idempotency_key) = idempotency_key {
+        match try_processing(&pool, idempotency_key, &form.fingerprint())
+            .await
+            .context("Failed to claim the idempotency key")?
+        {
+            NextAction::StartProcessing => {}
+            NextAction::ReturnSavedResponse(saved_response) => return Ok(saved_response),
+            NextAction::StillProcessing => return Err(SubscribeError::StillProcessing),
+            NextAction::KeyReused => return Err(SubscribeError::IdempotencyKeyReused),
+        }
+    }
+    let outcome = register_subscriber(
+        form.0,
+        &pool,
+        email_client.get_ref(),
         &base_url.0,
-        &subscription_token,
+        &metadata_fields.0,
+        hide_existence.0,
+        &code_settings,
+        &email_settings,
+        retries.0,
     )
+    .await;
+    let response = match (outcome, idempotency_key) {
+        (Ok(response), Some(idempotency_key)) => {
+            // The subscriber is stored and emailed, so the client still hears
+            // about it. The claim is taken over once it counts as abandoned.
+            if let Err(e) = save_response(&pool, idempotency_key, &response).await {
+                tracing::error!(
+                    error.cause_chain = ?e,
+                    "Failed to save the subscribe response"
+                );
+            }
+            response
+        }
+        (Ok(response), None) => response,
+        (Err(e), Some(idempotency_key)) => {
+            release_key(&pool, idempotency_key)
+                .await
+                .context("Failed to release the idempotency key")?;
+            return Err(e);
+        }
+        (Err(e), None) => return Err(e),
+    };
+    Ok(response)
+}
+
+// Shared by every subscription entry point: store the pending subscriber
+// and send them a confirmation email
+#[allow(clippy::too_many_arguments)]
+pub async fn register_subscriber(
+    form: FormData,
+    pool: &PgPool,
+    email_client: &dyn EmailProvider,
+    base_url: &str,
+    metadata_fields: &[String],
+    hide_existence: bool,
+    code_settings: &ConfirmationCodeSettings,
+    email_settings: &ConfirmationEmailSettings,
+    retries: u32,
+) -> Result<HttpResponse, SubscribeError> {
+    let metadata = select_metadata(&form.extra, metadata_fields);
+    let new_subscriber = form.try_into().map_err(SubscribeError::ValidationError)?;
+    let confirmation_code = generate_confirmation_code();
+    let code_expires_at = Utc::now() + code_settings.ttl();
+    // A rolled back transaction leaves nothing behind, so it is rerun whole.
+    // A unique violation is not transient, so it comes straight back here.
+    let stored = retry_transient(retries, || {
+        store_pending_subscriber(
+            pool,
+            &new_subscriber,
+            &metadata,
+            &confirmation_code,
+            code_expires_at,
+        )
+    })
+    .await;
+    let subscription_token = match stored {
+        Ok(subscription_token) => subscription_token,
+        Err(e) if is_unique_violation(&e) => {
+            let existing =
+                retry_transient(retries, || subscriber_status(pool, &new_subscriber.email))
+                    .await
+                    .context("Failed to look up the existing subscriber")?;
+            match existing {
+                // Still pending, e.g. the first confirmation email never arrived,
+                // so they get a fresh link and code
+                Some((subscriber_id, status)) if status == "pending_confirmation" => {
+                    retry_transient(retries, || {
+                        reissue_confirmation(
+                            pool,
+                            subscriber_id,
+                            &confirmation_code,
+                            code_expires_at,
+                        )
+                    })
+                    .await
+                    .context("Failed to reissue the confirmation for a pending subscriber")?
+                }
+                // Answer exactly like a fresh subscription when operators hide
+                // existence, including the time spent sending an email
+                _ if hide_existence => {
+                    let custom_id = email_custom_id("already-subscribed");
+                    send_with_retries(email_settings, || {
+                        send_already_subscribed_email(email_client, &new_subscriber, &custom_id)
+                    })
+                    .await
+                    .context("Failed to send an already subscribed email")?;
+                    return Ok(HttpResponse::Ok().finish());
+                }
+                _ => return Err(SubscribeError::AlreadySubscribed),
+            }
+        }
+        Err(e) => {
+            return Err(anyhow::Error::new(e)
+                .context("Failed to store a new subscriber in the database")
+                .into())
+        }
+    };
+    let confirmation_link = ConfirmationLink::new(base_url, subscription_token.as_ref())
+        .context("Failed to build the confirmation link")?;
+    let custom_id = email_custom_id("confirmation");
+    send_with_retries(email_settings, || {
+        send_confirmation_email(
+            email_client,
+            &new_subscriber,
+            &confirmation_link,
+            &confirmation_code,
+            &custom_id,
+        )
+    })
     .await
     .context("Failed to send a confirmation email")?;
 
     Ok(HttpResponse::Ok().finish())
 }
 
+#[tracing::instrument(
+    name = "Store a new pending subscriber",
+    skip(pool, new_subscriber, metadata, confirmation_code)
+)]
+async fn store_pending_subscriber(
+    pool: &PgPool,
+    new_subscriber: &NewSubscriber,
+    metadata: &serde_json::Value,
+    confirmation_code: &str,
+    code_expires_at: DateTime<Utc>,
+) -> Result<SubscriptionToken, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscriber_id = insert_subscriber(&mut transaction, new_subscriber, metadata).await?;
+    let subscription_token = store_new_token(&mut transaction, subscriber_id)
+        .await
+        .map_err(|e| e.0)?;
+    store_confirmation_code(
+        &mut transaction,
+        subscriber_id,
+        confirmation_code,
+        code_expires_at,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(subscription_token)
+}
+
+// Earlier links stay valid, the code is replaced and its attempts reset
+#[tracing::instrument(
+    name = "Reissue the confirmation for a pending subscriber",
+    skip(pool, confirmation_code)
+)]
+async fn reissue_confirmation(
+    pool: &PgPool,
+    subscriber_id: Uuid,
+    confirmation_code: &str,
+    code_expires_at: DateTime<Utc>,
+) -> Result<SubscriptionToken, sqlx::Error> {
+    let mut transaction = pool.begin().await?;
+    let subscription_token = store_new_token(&mut transaction, subscriber_id)
+        .await
+        .map_err(|e| e.0)?;
+    store_confirmation_code(
+        &mut transaction,
+        subscriber_id,
+        confirmation_code,
+        code_expires_at,
+    )
+    .await?;
+    transaction.commit().await?;
+    Ok(subscription_token)
+}
+
+#[tracing::instrument(name = "Look up a subscriber by email", skip(pool, email))]
+async fn subscriber_status(
+    pool: &PgPool,
+    email: &SubscriberEmail,
+) -> Result<Option<(Uuid, String)>, sqlx::Error> {
+    let row = sqlx::query!(
+        "SELECT id, status FROM subscriptions WHERE email = $1",
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(row.map(|r| (r.id, r.status)))
+}
+
 #[tracing::instrument(
     name = "Saving new subscriber details in the database",
-    skip(transaction, new_subscriber)
+    skip(transaction, new_subscriber, metadata)
 )]
 pub async fn insert_subscriber(
     transaction: &mut Transaction<'_, Postgres>,
     new_subscriber: &NewSubscriber,
+    metadata: &serde_json::Value,
 ) -> Result<Uuid, sqlx::Error> {
     let subscriber_id = Uuid::new_v4();
     sqlx::query!(
         r#"
-        INSERT INTO subscriptions (id, email, name, subscribed_at, status)
-        VALUES ($1, $2, $3, $4, 'pending_confirmation')
+        INSERT INTO subscriptions (id, email, name, subscribed_at, status, metadata)
+        VALUES ($1, $2, $3, $4, 'pending_confirmation', $5)
         "#,
         subscriber_id,
         new_subscriber.email.as_ref(),
         new_subscriber.name.as_ref(),
         Utc::now(),
+        metadata,
     )
     .execute(transaction)
     .await?;
     Ok(subscriber_id)
 }
 
+// The link is time-sensitive, so a transient provider failure is retried
+// a few times before giving up on the request
+async fn send_with_retries<F, Fut>(
+    settings: &ConfirmationEmailSettings,
+    mut send: F,
+) -> Result<(), EmailClientError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<(), EmailClientError>>,
+{
+    let mut retries_left = settings.retries;
+    loop {
+        match send().await {
+            Err(e) if e.is_provider_failure() && retries_left > 0 => {
+                retries_left -= 1;
+                tracing::warn!(
+                    error.message = %e,
+                    retries_left,
+                    "Failed to send a subscription email, retrying"
+                );
+                actix_web::rt::time::sleep(settings.retry_delay()).await;
+            }
+            outcome => return outcome,
+        }
+    }
+}
+
+// Stays the same across retries, so the provider can drop a duplicate
+// if an attempt we saw fail was in fact delivered
+fn email_custom_id(kind: &str) -> String {
+    format!("{}-{}", kind, Uuid::new_v4())
+}
+
 #[tracing::instrument(
     name = "Send a confirmation email to a new subscriber",
-    skip(email_client, new_subscriber, base_url, subscription_token)
+    skip(email_client, new_subscriber, confirmation_link, confirmation_code)
 )]
 pub async fn send_confirmation_email(
-    email_client: &EmailClient,
-    new_subscriber: NewSubscriber,
-    base_url: &str,
-    subscription_token: &str,
-) -> Result<(), reqwest::Error> {
-    let confirmation_link = format!(
-        "{}/subscriptions/confirm?subscription_token={}",
-        base_url, subscription_token
-    );
+    email_client: &dyn EmailProvider,
+    new_subscriber: &NewSubscriber,
+    confirmation_link: &ConfirmationLink,
+    confirmation_code: &str,
+    custom_id: &str,
+) -> Result<(), EmailClientError> {
     let plain_body = &format!(
-        "Welcome to our newsletter!\nVisit {} to confirm your subscription.",
-        confirmation_link
+        "Welcome to our newsletter!\nVisit {} to confirm your subscription.\n\
+            Or enter your confirmation code: {}",
+        confirmation_link.as_ref(),
+        confirmation_code
     );
     let html_body = &format!(
         "Welcome to our newsletter!<br />\
-            Click <a href=\"{}\">here</a> to confirm your subscription.",
-        confirmation_link
+            Click <a href=\"{}\">here</a> to confirm your subscription.<br />\
+            Or enter your confirmation code: {}",
+        confirmation_link.as_ref(),
+        confirmation_code
     );
 
     email_client
-        .send_email(new_subscriber.email, "Welcome!", html_body, plain_body)
+        .send_email_with_custom_id(
+            new_subscriber.email.clone(),
+            "Welcome!",
+            html_body,
+            plain_body,
+            Some(custom_id),
+        )
+        .await
+}
+
+#[tracing::instrument(
+    name = "Tell a confirmed subscriber they are already subscribed",
+    skip(email_client, new_subscriber)
+)]
+async fn send_already_subscribed_email(
+    email_client: &dyn EmailProvider,
+    new_subscriber: &NewSubscriber,
+    custom_id: &str,
+) -> Result<(), EmailClientError> {
+    email_client
+        .send_email_with_custom_id(
+            new_subscriber.email.clone(),
+            "You are already subscribed",
+            "This email address is already subscribed to our newsletter, \
+                there is nothing else to do.",
+            "This email address is already subscribed to our newsletter, \
+                there is nothing else to do.",
+            Some(custom_id),
+        )
         .await
 }
 
+#[tracing::instrument(
+    name = "Generate and store a new subscription token",
+    skip(transaction)
+)]
+pub async fn store_new_token(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+) -> Result<SubscriptionToken, StoreTokenError> {
+    let mut attempt = 1;
+    loop {
+        let subscription_token = SubscriptionToken::generate();
+        // Insert inside a savepoint, so a collision doesn't abort the whole transaction
+        let mut savepoint = transaction.begin().await.map_err(StoreTokenError)?;
+        match store_token(&mut savepoint, subscriber_id, &subscription_token).await {
+            Ok(()) => {
+                savepoint.commit().await.map_err(StoreTokenError)?;
+                return Ok(subscription_token);
+            }
+            Err(e) if attempt < MAX_TOKEN_ATTEMPTS && is_unique_violation(&e.0) => {
+                tracing::warn!(attempt, "Subscription token collision, regenerating");
+                savepoint.rollback().await.map_err(StoreTokenError)?;
+                attempt += 1;
+            }
+            Err(e) => return Err(e),
+        }
+    }
+}
+
 #[tracing::instrument(
     name = "Store subscription token in the database",
     skip(subscription_token, transaction)
@@ -175,11 +547,11 @@ pub async fn send_confirmation_email(
 pub async fn store_token(
     transaction: &mut Transaction<'_, Postgres>,
     subscriber_id: Uuid,
-    subscription_token: &str,
+    subscription_token: &SubscriptionToken,
 ) -> Result<(), StoreTokenError> {
     sqlx::query!(
         r#"INSERT INTO subscription_tokens (subscription_token, subscriber_id) VALUES ($1, $2)"#,
-        subscription_token,
+        subscription_token.as_ref(),
         subscriber_id
     )
     .execute(transaction)
@@ -188,23 +560,71 @@ pub async fn store_token(
     Ok(())
 }
 
-fn generate_subscription_token() -> String {
-    let mut rng = thread_rng();
-    std::iter::repeat_with(|| rng.sample(Alphanumeric))
-        .map(char::from)
-        .take(25)
-        .collect()
+// Keep only the extra form fields the operator chose to store
+fn select_metadata(extra: &HashMap<String, String>, allowed: &[String]) -> serde_json::Value {
+    let selected: serde_json::Map<String, serde_json::Value> = extra
+        .iter()
+        .filter(|(field, _)| allowed.contains(field))
+        .map(|(field, value)| (field.clone(), serde_json::Value::String(value.clone())))
+        .collect();
+    serde_json::Value::Object(selected)
 }
 
-fn error_chain_fmt(
-    e: &impl std::error::Error,
-    f: &mut std::fmt::Formatter<'_>,
-) -> std::fmt::Result {
-    writeln!(f, "{}\n", e)?;
-    let mut current = e.source();
-    while let Some(cause) = current {
-        writeln!(f, "Caused by:\n\t{}", cause)?;
-        current = cause.source();
+pub fn subscription_form_error_handler(
+    err: UrlencodedError,
+    _req: &HttpRequest,
+) -> actix_web::Error {
+    let body = match &err {
+        UrlencodedError::Parse(e) if e.to_string() == INVALID_EMAIL => {
+            tracing::debug!(rejected_fields = ?["email"], "Rejected invalid subscription form fields");
+            INVALID_EMAIL
+        }
+        _ => {
+            // Other parse errors name the missing or malformed field, not its value
+            tracing::debug!(error = %err, "Rejected malformed subscription form");
+            "Invalid subscription form data"
+        }
+    };
+    InternalError::from_response(err, HttpResponse::BadRequest().body(body)).into()
+}
+
+fn is_unique_violation(e: &sqlx::Error) -> bool {
+    match e {
+        sqlx::Error::Database(e) => e.code().as_deref() == Some("23505"),
+        _ => false,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::routes::SubscribeError;
+    use actix_web::ResponseError;
+    use anyhow::Context;
+    use reqwest::StatusCode;
+
+    #[test]
+    fn a_validation_error_is_a_400() {
+        let e = SubscribeError::ValidationError("Invalid subscription form fields: name".into());
+        assert_eq!(e.status_code(), StatusCode::BAD_REQUEST);
+    }
+
+    #[test]
+    fn a_database_failure_is_a_500() {
+        let e: SubscribeError = Err::<(), _>(sqlx::Error::RowNotFound)
+            .context("Failed to insert a new subscriber in the database")
+            .unwrap_err()
+            .into();
+        assert_eq!(e.status_code(), StatusCode::INTERNAL_SERVER_ERROR);
+    }
+
+    #[test]
+    fn the_debug_output_includes_the_error_chain() {
+        let e: SubscribeError = Err::<(), _>(sqlx::Error::RowNotFound)
+            .context("Failed to insert a new subscriber in the database")
+            .unwrap_err()
+            .into();
+        let output = format!("{:?}", e);
+        assert!(output.contains("Failed to insert a new subscriber in the database"));
+        assert!(output.contains("Caused by"));
     }
-    Ok(())
 }
@@ -0,0 +1,220 @@
+use crate::configuration::ConfirmationCodeSettings;
+use crate::domain::SubscriberEmail;
+use crate::email_client::FailoverEmailClient;
+use crate::error::{error_chain_fmt, error_response, unexpected_error_status};
+use crate::routes::{confirm_subscriber, delete_old_token, deserialize_email, spawn_welcome_email};
+use crate::startup::WelcomeEmail;
+use crate::token_cache::TokenCache;
+use actix_web::{web, HttpResponse, ResponseError};
+use anyhow::Context;
+use chrono::{DateTime, Utc};
+use rand::{thread_rng, Rng};
+use reqwest::StatusCode;
+use sha2::{Digest, Sha256};
+use sqlx::{PgPool, Postgres, Transaction};
+use subtle::ConstantTimeEq;
+use uuid::Uuid;
+
+#[derive(serde::Deserialize)]
+pub struct ConfirmCodeData {
+    // The default parse error would echo the address into the response and logs
+    #[serde(deserialize_with = "deserialize_email")]
+    email: SubscriberEmail,
+    code: String,
+}
+
+#[derive(thiserror::Error)]
+pub enum ConfirmCodeError {
+    #[error("The confirmation code is invalid or has expired")]
+    InvalidCode,
+    #[error("Too many failed attempts, use the link in the confirmation email instead")]
+    TooManyAttempts,
+    #[error(transparent)]
+    UnexpectedError(#[from] anyhow::Error),
+}
+
+impl std::fmt::Debug for ConfirmCodeError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for ConfirmCodeError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            ConfirmCodeError::InvalidCode => StatusCode::UNAUTHORIZED,
+            ConfirmCodeError::TooManyAttempts => StatusCode::TOO_MANY_REQUESTS,
+            ConfirmCodeError::UnexpectedError(e) => unexpected_error_status(e),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        error_response(self)
+    }
+}
+
+struct ReservedAttempt {
+    subscriber_id: Uuid,
+    code_hash: String,
+    expires_at: DateTime<Utc>,
+}
+
+#[tracing::instrument(
+    name = "Confirm a pending subscriber with a code",
+    skip(data, pool, settings, email_client, welcome_email, token_cache)
+)]
+pub async fn confirm_with_code(
+    data: web::Json<ConfirmCodeData>,
+    pool: web::Data<PgPool>,
+    settings: web::Data<ConfirmationCodeSettings>,
+    email_client: web::Data<FailoverEmailClient>,
+    welcome_email: web::Data<WelcomeEmail>,
+    token_cache: web::Data<TokenCache>,
+) -> Result<HttpResponse, ConfirmCodeError> {
+    // The attempt is counted before the code is compared, so parallel guesses
+    // cannot all slip in under the limit
+    let attempt = reserve_attempt(&data.email, settings.max_attempts, &pool)
+        .await
+        .context("Failed to record a confirmation attempt")?;
+    let attempt = match attempt {
+        Some(attempt) => attempt,
+        None => {
+            let has_code = has_stored_code(&data.email, &pool)
+                .await
+                .context("Failed to retrieve the confirmation code")?;
+            return Err(if has_code {
+                ConfirmCodeError::TooManyAttempts
+            } else {
+                ConfirmCodeError::InvalidCode
+            });
+        }
+    };
+    if attempt.expires_at < Utc::now() {
+        return Err(ConfirmCodeError::InvalidCode);
+    }
+    let code_hash = hash_code(attempt.subscriber_id, &data.code);
+    if !bool::from(code_hash.as_bytes().ct_eq(attempt.code_hash.as_bytes())) {
+        return Err(ConfirmCodeError::InvalidCode);
+    }
+
+    let mut transaction = pool
+        .begin()
+        .await
+        .context("Failed to acquire a Postgres connection from the pool")?;
+    let confirmed = confirm_subscriber(attempt.subscriber_id, &mut transaction)
+        .await
+        .context("Failed to set subscriber status to confirmed")?;
+    // Confirmed through the link in the meantime
+    if !confirmed {
+        return Ok(HttpResponse::Ok().finish());
+    }
+    let deleted_tokens = delete_old_token(attempt.subscriber_id, &mut transaction)
+        .await
+        .context("Failed to delete old subscriber token")?;
+    delete_confirmation_code(attempt.subscriber_id, &mut transaction)
+        .await
+        .context("Failed to delete the confirmation code")?;
+    transaction
+        .commit()
+        .await
+        .context("Failed to commit SQL transaction to confirm user")?;
+    for token in &deleted_tokens {
+        token_cache.remove(token);
+    }
+    spawn_welcome_email(attempt.subscriber_id, pool, email_client, welcome_email);
+    Ok(HttpResponse::Ok().finish())
+}
+
+#[tracing::instrument(name = "Reserve a confirmation attempt", skip(email, pool))]
+async fn reserve_attempt(
+    email: &SubscriberEmail,
+    max_attempts: i32,
+    pool: &PgPool,
+) -> Result<Option<ReservedAttempt>, sqlx::Error> {
+    sqlx::query_as!(
+        ReservedAttempt,
+        r#"
+        UPDATE confirmation_codes c
+        SET failed_attempts = c.failed_attempts + 1
+        FROM subscriptions s
+        WHERE s.id = c.subscriber_id AND s.email = $1 AND c.failed_attempts < $2
+        RETURNING c.subscriber_id, c.code_hash, c.expires_at
+        "#,
+        email.as_ref(),
+        max_attempts
+    )
+    .fetch_optional(pool)
+    .await
+}
+
+#[tracing::instrument(name = "Check for a stored confirmation code", skip(email, pool))]
+async fn has_stored_code(email: &SubscriberEmail, pool: &PgPool) -> Result<bool, sqlx::Error> {
+    let result = sqlx::query!(
+        r#"
+        SELECT c.subscriber_id
+        FROM confirmation_codes c
+        JOIN subscriptions s ON s.id = c.subscriber_id
+        WHERE s.email = $1
+        "#,
+        email.as_ref()
+    )
+    .fetch_optional(pool)
+    .await?;
+    Ok(result.is_some())
+}
+
+// Replaces any earlier code, e.g. when a pending subscriber signs up again
+#[tracing::instrument(
+    name = "Store the confirmation code in the database",
+    skip(transaction, code)
+)]
+pub async fn store_confirmation_code(
+    transaction: &mut Transaction<'_, Postgres>,
+    subscriber_id: Uuid,
+    code: &str,
+    expires_at: DateTime<Utc>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        r#"
+        INSERT INTO confirmation_codes (subscriber_id, code_hash, expires_at)
+        VALUES ($1, $2, $3)
+        ON CONFLICT (subscriber_id) DO UPDATE
+        SET code_hash = EXCLUDED.code_hash,
+            expires_at = EXCLUDED.expires_at,
+            failed_attempts = 0
+        "#,
+        subscriber_id,
+        hash_code(subscriber_id, code),
+        expires_at
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+#[tracing::instrument(
+    name = "Deleting the confirmation code",
+    skip(subscriber_id, transaction)
+)]
+pub async fn delete_confirmation_code(
+    subscriber_id: Uuid,
+    transaction: &mut Transaction<'_, Postgres>,
+) -> Result<(), sqlx::Error> {
+    sqlx::query!(
+        "DELETE FROM confirmation_codes WHERE subscriber_id = $1",
+        subscriber_id
+    )
+    .execute(transaction)
+    .await?;
+    Ok(())
+}
+
+pub fn generate_confirmation_code() -> String {
+    format!("{:06}", thread_rng().gen_range(0..1_000_000))
+}
+
+// Codes are only stored hashed, salted with the subscriber id
+fn hash_code(subscriber_id: Uuid, code: &str) -> String {
+    let digest = Sha256::digest(format!("{}:{}", subscriber_id, code).as_bytes());
+    hex::encode(digest)
+}
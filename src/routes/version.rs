@@ -0,0 +1,17 @@
+use actix_web::HttpResponse;
+
+#[derive(serde::Serialize)]
+struct BuildInformation {
+    version: &'static str,
+    git_sha: &'static str,
+    build_timestamp: &'static str,
+}
+
+pub async fn version() -> HttpResponse {
+    // GIT_SHA and BUILD_TIMESTAMP are set by build.rs
+    HttpResponse::Ok().json(BuildInformation {
+        version: env!("CARGO_PKG_VERSION"),
+        git_sha: env!("GIT_SHA"),
+        build_timestamp: env!("BUILD_TIMESTAMP"),
+    })
+}
@@ -0,0 +1,208 @@
+use crate::configuration::{ConfirmationCodeSettings, ConfirmationEmailSettings, WebhookSettings};
+use crate::email_client::FailoverEmailClient;
+use crate::error::{error_chain_fmt, error_response};
+use crate::routes::{register_subscriber, FormData, SubscribeError};
+use crate::startup::{
+    ApplicationBaseUrl, HideSubscriptionExistence, SubscriberMetadataFields, TransientDbRetries,
+};
+use actix_web::{web, HttpRequest, HttpResponse, ResponseError};
+use hmac::{Hmac, Mac};
+use reqwest::StatusCode;
+use secrecy::ExposeSecret;
+use sha2::{Sha256, Sha512};
+use sqlx::PgPool;
+
+#[derive(thiserror::Error, Debug, PartialEq, Eq)]
+pub enum SignatureError {
+    #[error("No webhook signing secret is configured")]
+    MissingSecret,
+    #[error("The webhook signature is missing or not hex encoded")]
+    Malformed,
+    #[error("The webhook signature does not match the body")]
+    Mismatch,
+    #[error("{0} is not a supported webhook signature algorithm")]
+    UnsupportedAlgorithm(String),
+}
+
+#[derive(thiserror::Error)]
+pub enum SubscribeWebhookError {
+    #[error("The webhook signature is missing or invalid")]
+    InvalidSignature(#[source] SignatureError),
+    #[error("The webhook signature cannot be verified")]
+    Misconfigured(#[source] SignatureError),
+    #[error("The webhook payload is not a valid subscription: {0}")]
+    InvalidPayload(#[from] serde_json::Error),
+    #[error(transparent)]
+    Subscribe(#[from] SubscribeError),
+}
+
+impl std::fmt::Debug for SubscribeWebhookError {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        error_chain_fmt(self, f)
+    }
+}
+
+impl ResponseError for SubscribeWebhookError {
+    fn status_code(&self) -> reqwest::StatusCode {
+        match self {
+            SubscribeWebhookError::InvalidSignature(_) => StatusCode::UNAUTHORIZED,
+            SubscribeWebhookError::Misconfigured(_) => StatusCode::INTERNAL_SERVER_ERROR,
+            SubscribeWebhookError::InvalidPayload(_) => StatusCode::BAD_REQUEST,
+            SubscribeWebhookError::Subscribe(e) => e.status_code(),
+        }
+    }
+
+    fn error_response(&self) -> HttpResponse {
+        match self {
+            SubscribeWebhookError::Subscribe(e) => e.error_response(),
+            _ => error_response(self),
+        }
+    }
+}
+
+#[tracing::instrument(
+    name = "Adding a new subscriber from a webhook",
+    skip(
+        request,
+        body,
+        webhook,
+        pool,
+        email_client,
+        base_url,
+        metadata_fields,
+        hide_existence,
+        code_settings,
+        email_settings,
+        retries
+    )
+)]
+#[allow(clippy::too_many_arguments)]
+pub async fn subscribe_webhook(
+    request: HttpRequest,
+    body: web::Bytes,
+    webhook: web::Data<WebhookSettings>,
+    pool: web::Data<PgPool>,
+    email_client: web::Data<FailoverEmailClient>,
+    base_url: web::Data<ApplicationBaseUrl>,
+    metadata_fields: web::Data<SubscriberMetadataFields>,
+    hide_existence: web::Data<HideSubscriptionExistence>,
+    code_settings: web::Data<ConfirmationCodeSettings>,
+    email_settings: web::Data<ConfirmationEmailSettings>,
+    retries: web::Data<TransientDbRetries>,
+) -> Result<HttpResponse, SubscribeWebhookError> {
+    // The signature covers the raw body, so verify it before parsing anything
+    let signature = request
+        .headers()
+        .get(webhook.signature_header.as_str())
+        .and_then(|value| value.to_str().ok())
+        .unwrap_or_default();
+    verify_signature(&webhook, &body, signature).map_err(|e| match e {
+        SignatureError::UnsupportedAlgorithm(_) => SubscribeWebhookError::Misconfigured(e),
+        _ => SubscribeWebhookError::InvalidSignature(e),
+    })?;
+    let form: FormData = serde_json::from_slice(&body)?;
+    let response = register_subscriber(
+        form,
+        &pool,
+        email_client.get_ref(),
+        &base_url.0,
+        &metadata_fields.0,
+        hide_existence.0,
+        &code_settings,
+        &email_settings,
+        retries.0,
+    )
+    .await?;
+    Ok(response)
+}
+
+// Hex-encoded HMAC of the body using the configured algorithm, compared in
+// constant time. Without a configured secret every request is rejected.
+pub fn verify_signature(
+    webhook: &WebhookSettings,
+    body: &[u8],
+    signature: &str,
+) -> Result<(), SignatureError> {
+    let secret = webhook
+        .secret
+        .as_ref()
+        .ok_or(SignatureError::MissingSecret)?;
+    let key = secret.expose_secret().as_bytes();
+    let signature = hex::decode(signature).map_err(|_| SignatureError::Malformed)?;
+    let verified = match webhook.algorithm.as_str() {
+        "hmac-sha256" => verify_hmac::<Hmac<Sha256>>(key, body, &signature),
+        "hmac-sha512" => verify_hmac::<Hmac<Sha512>>(key, body, &signature),
+        other => return Err(SignatureError::UnsupportedAlgorithm(other.into())),
+    };
+    if verified {
+        Ok(())
+    } else {
+        Err(SignatureError::Mismatch)
+    }
+}
+
+fn verify_hmac<M: Mac + hmac::digest::KeyInit>(key: &[u8], body: &[u8], signature: &[u8]) -> bool {
+    let mut mac = <M as Mac>::new_from_slice(key).expect("HMAC can take a key of any size");
+    mac.update(body);
+    mac.verify_slice(signature).is_ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::configuration::WebhookSettings;
+    use crate::routes::{verify_signature, SignatureError};
+    use claims::{assert_err_eq, assert_ok};
+    use hmac::{Hmac, Mac};
+    use secrecy::Secret;
+    use sha2::Sha256;
+
+    const BODY: &[u8] = br#"{"name":"mr test","email":"mr_t@test.com"}"#;
+
+    fn settings(algorithm: &str) -> WebhookSettings {
+        WebhookSettings {
+            secret: Some(Secret::new("webhook-secret".into())),
+            algorithm: algorithm.into(),
+            ..WebhookSettings::default()
+        }
+    }
+
+    fn sign(secret: &str) -> String {
+        let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+        mac.update(BODY);
+        hex::encode(mac.finalize().into_bytes())
+    }
+
+    #[test]
+    fn a_correct_signature_is_accepted() {
+        assert_ok!(verify_signature(
+            &settings("hmac-sha256"),
+            BODY,
+            &sign("webhook-secret")
+        ));
+    }
+
+    #[test]
+    fn a_signature_made_with_another_secret_is_rejected() {
+        assert_err_eq!(
+            verify_signature(&settings("hmac-sha256"), BODY, &sign("another-secret")),
+            SignatureError::Mismatch
+        );
+    }
+
+    #[test]
+    fn an_unsupported_algorithm_is_reported() {
+        assert_err_eq!(
+            verify_signature(&settings("md5"), BODY, &sign("webhook-secret")),
+            SignatureError::UnsupportedAlgorithm("md5".into())
+        );
+    }
+
+    #[test]
+    fn nothing_is_accepted_without_a_secret() {
+        let settings = WebhookSettings::default();
+        assert_err_eq!(
+            verify_signature(&settings, BODY, &sign("webhook-secret")),
+            SignatureError::MissingSecret
+        );
+    }
+}
@@ -0,0 +1,174 @@
+use actix_web::web;
+use std::sync::Mutex;
+
+/// Counts requests being served and, once a shutdown starts, those that were
+/// already in flight and ran to completion, so the shutdown can report how
+/// many were drained and how many were cut off.
+#[derive(Default)]
+pub struct InFlightRequests(Mutex<Counts>);
+
+// Behind one lock, so a request starting as the drain begins is counted on
+// exactly one side of it
+#[derive(Default)]
+struct Counts {
+    in_flight: usize,
+    draining: bool,
+    drained: usize,
+}
+
+/// Taken when a shutdown starts, to compare against once the server stops.
+#[derive(Clone, Copy, Debug)]
+pub struct DrainStart {
+    in_flight: usize,
+}
+
+#[derive(Debug, PartialEq, Eq)]
+pub struct DrainReport {
+    pub in_flight_at_shutdown: usize,
+    pub drained: usize,
+    pub cut_off: usize,
+}
+
+impl DrainReport {
+    pub fn completed(&self) -> bool {
+        self.cut_off == 0
+    }
+}
+
+impl InFlightRequests {
+    pub fn start(requests: web::Data<Self>) -> InFlightGuard {
+        let before_drain = {
+            let mut counts = requests.0.lock().unwrap();
+            counts.in_flight += 1;
+            !counts.draining
+        };
+        InFlightGuard {
+            requests,
+            before_drain,
+        }
+    }
+
+    pub fn begin_drain(&self) -> DrainStart {
+        let mut counts = self.0.lock().unwrap();
+        counts.draining = true;
+        DrainStart {
+            in_flight: counts.in_flight,
+        }
+    }
+
+    // Pausing the server only stops new connections, open keep-alive ones
+    // still send requests. Those started after the drain began are left out.
+    pub fn report(&self, start: DrainStart) -> DrainReport {
+        let drained = self.0.lock().unwrap().drained;
+        DrainReport {
+            in_flight_at_shutdown: start.in_flight,
+            drained,
+            cut_off: start.in_flight - drained,
+        }
+    }
+}
+
+/// Held for the duration of a request. Dropping it without calling `finish`,
+/// e.g. when the shutdown timeout cancels the request, counts as a cut off.
+pub struct InFlightGuard {
+    requests: web::Data<InFlightRequests>,
+    before_drain: bool,
+}
+
+impl InFlightGuard {
+    pub fn finish(self) {
+        let mut counts = self.requests.0.lock().unwrap();
+        if self.before_drain && counts.draining {
+            counts.drained += 1;
+        }
+    }
+}
+
+impl Drop for InFlightGuard {
+    fn drop(&mut self) {
+        self.requests.0.lock().unwrap().in_flight -= 1;
+    }
+}
+
+/// Resolves on Ctrl-C, or on SIGTERM where there is one.
+pub async fn shutdown_signal() {
+    #[cfg(unix)]
+    {
+        use tokio::signal::unix::{signal, SignalKind};
+        let mut terminate = signal(SignalKind::terminate()).expect("Failed to listen for SIGTERM");
+        tokio::select! {
+            _ = tokio::signal::ctrl_c() => {}
+            _ = terminate.recv() => {}
+        }
+    }
+    #[cfg(not(unix))]
+    {
+        let _ = tokio::signal::ctrl_c().await;
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::shutdown::{DrainReport, InFlightRequests};
+    use actix_web::web;
+
+    #[test]
+    fn requests_finishing_during_the_drain_are_counted_as_drained() {
+        let requests = web::Data::new(InFlightRequests::default());
+        let first = InFlightRequests::start(requests.clone());
+        let second = InFlightRequests::start(requests.clone());
+
+        let start = requests.begin_drain();
+        first.finish();
+        second.finish();
+
+        let report = requests.report(start);
+        assert_eq!(
+            report,
+            DrainReport {
+                in_flight_at_shutdown: 2,
+                drained: 2,
+                cut_off: 0,
+            }
+        );
+        assert!(report.completed());
+    }
+
+    #[test]
+    fn requests_dropped_before_finishing_are_counted_as_cut_off() {
+        let requests = web::Data::new(InFlightRequests::default());
+        let finished = InFlightRequests::start(requests.clone());
+        let cancelled = InFlightRequests::start(requests.clone());
+
+        let start = requests.begin_drain();
+        finished.finish();
+        drop(cancelled);
+
+        let report = requests.report(start);
+        assert_eq!(report.drained, 1);
+        assert_eq!(report.cut_off, 1);
+        assert!(!report.completed());
+    }
+
+    #[test]
+    fn requests_started_during_the_drain_are_not_counted() {
+        let requests = web::Data::new(InFlightRequests::default());
+        let cancelled = InFlightRequests::start(requests.clone());
+
+        let start = requests.begin_drain();
+        // Sent over a keep-alive connection after the drain began
+        let late = InFlightRequests::start(requests.clone());
+        late.finish();
+        drop(cancelled);
+
+        let report = requests.report(start);
+        assert_eq!(
+            report,
+            DrainReport {
+                in_flight_at_shutdown: 1,
+                drained: 0,
+                cut_off: 1,
+            }
+        );
+    }
+}
@@ -1,52 +1,107 @@
 use crate::{
-    configuration::{DatabaseSettings, Settings},
-    email_client::EmailClient,
-    routes::{confirm, health_check, subscribe},
+    configuration::{
+        get_configuration, ApplicationSettings, ConfirmationCodeSettings,
+        ConfirmationEmailSettings, DatabaseSettings, SecurityHeadersSettings, Settings,
+        TokenCacheSettings, WebhookSettings, WelcomeEmailSettings,
+    },
+    email_client::FailoverEmailClient,
+    routes::{
+        confirm, confirm_with_code, health_check, subscribe, subscribe_webhook, subscription_form,
+        subscription_form_error_handler, version,
+    },
+    shutdown::{shutdown_signal, InFlightRequests},
 };
-use actix_web::{dev::Server, web, App, HttpServer};
+use actix_web::dev::{Server, Service, ServiceRequest};
+use actix_web::http::header::{ContentType, RETRY_AFTER};
+use actix_web::middleware::{DefaultHeaders, NormalizePath};
+use actix_web::{web, App, HttpResponse, HttpServer};
 use sqlx::postgres::PgPoolOptions;
 use sqlx::PgPool;
 use std::net::TcpListener;
+use std::sync::atomic::{AtomicBool, Ordering};
+use tokio::sync::Semaphore;
 use tracing_actix_web::TracingLogger;
 
+// Requests still running this long after a shutdown starts are cut off
+const SHUTDOWN_TIMEOUT_SECONDS: u64 = 30;
+
 pub struct Application {
     port: u16,
     server: Server,
+    in_flight: web::Data<InFlightRequests>,
+    maintenance_mode: web::Data<MaintenanceMode>,
 }
 
 pub struct ApplicationBaseUrl(pub String);
 
+pub struct AllowedRedirects(pub Vec<String>);
+
+pub struct SubscriberMetadataFields(pub Vec<String>);
+
+pub struct HideSubscriptionExistence(pub bool);
+
+pub struct TransientDbRetries(pub u32);
+
+pub struct StartedAt(pub std::time::Instant);
+
+// Admission control for subscribe, so a signup spike cannot exhaust the pool
+pub struct SubscribeAdmission(pub Semaphore);
+
+pub struct CsrfProtection(pub bool);
+
+// Checked on every request, and re-read from the configuration on SIGHUP
+pub struct MaintenanceMode(pub AtomicBool);
+
+// Set when a welcome email should follow each confirmation
+pub struct WelcomeEmail(pub Option<WelcomeEmailSettings>);
+
 impl Application {
     pub async fn build(configuration: Settings) -> Result<Self, std::io::Error> {
+        // Secret values are redacted by their Debug implementation
+        tracing::info!(
+            host = %configuration.application.host,
+            port = configuration.application.port,
+            configuration = ?configuration,
+            "Starting application with the effective configuration"
+        );
         let connection_pool = get_connection_pool(&configuration.database);
+        let transient_db_retries = configuration.database.transient_retries;
+        let mut application = configuration.application;
+        application
+            .max_concurrent_subscribes
+            .get_or_insert(configuration.database.max_connections as usize);
 
-        let sender_email = configuration
-            .email_client
-            .sender()
-            .expect("Invalid sender email address");
-        let timeout = configuration.email_client.timeout();
-        let email_client = EmailClient::new(
-            configuration.email_client.base_url,
-            sender_email,
-            configuration.email_client.api_token,
-            configuration.email_client.secret_token,
-            timeout,
-        );
+        let email_client = configuration.email_client.failover_client();
 
-        let address = format!(
-            "{}:{}",
-            configuration.application.host, configuration.application.port
-        );
+        let address = format!("{}:{}", application.host, application.port);
         let listener = TcpListener::bind(address)?;
         let port = listener.local_addr().unwrap().port();
+        let in_flight = web::Data::new(InFlightRequests::default());
+        let maintenance_mode = web::Data::new(MaintenanceMode(AtomicBool::new(
+            application.maintenance_mode,
+        )));
         let server = run(
             listener,
             connection_pool,
+            in_flight.clone(),
+            maintenance_mode.clone(),
+            transient_db_retries,
             email_client,
-            configuration.application.base_url,
+            application,
+            configuration.security_headers,
+            configuration.webhook,
+            configuration.confirmation_code,
+            configuration.confirmation_email,
+            configuration.token_cache,
+            configuration.welcome_email,
         )?;
 
-        Ok(Self { port, server })
+        Ok(Self {
+            port,
+            server,
+            in_flight,
+            maintenance_mode,
+        })
     }
 
     pub fn port(&self) -> u16 {
@@ -54,38 +109,200 @@ impl Application {
     }
 
     pub async fn run_until_stopped(self) -> Result<(), std::io::Error> {
-        self.server.await
+        #[cfg(unix)]
+        tokio::spawn(reload_on_hangup(self.maintenance_mode.clone()));
+        let handle = self.server.handle();
+        let mut server = self.server;
+        // The server future handles pause and stop, so it is polled throughout
+        let start = tokio::select! {
+            outcome = &mut server => return outcome,
+            start = async {
+                shutdown_signal().await;
+                // Stop accepting new connections. Requests still arriving on
+                // open keep-alive connections are left out of the report.
+                handle.pause().await;
+                self.in_flight.begin_drain()
+            } => start,
+        };
+        tracing::info!("Shutting down, draining in-flight requests");
+        let (outcome, _) = tokio::join!(server, handle.stop(true));
+        let report = self.in_flight.report(start);
+        tracing::info!(
+            in_flight_at_shutdown = report.in_flight_at_shutdown,
+            drained = report.drained,
+            cut_off = report.cut_off,
+            drain_completed = report.completed(),
+            timeout_seconds = SHUTDOWN_TIMEOUT_SECONDS,
+            "Shutdown complete"
+        );
+        outcome
+    }
+}
+
+// Lets operators flip maintenance mode by editing the configuration and
+// sending SIGHUP, without a restart
+#[cfg(unix)]
+async fn reload_on_hangup(maintenance_mode: web::Data<MaintenanceMode>) {
+    use tokio::signal::unix::{signal, SignalKind};
+    let mut hangup = signal(SignalKind::hangup()).expect("Failed to listen for SIGHUP");
+    while hangup.recv().await.is_some() {
+        match get_configuration() {
+            Ok(configuration) => {
+                let enabled = configuration.application.maintenance_mode;
+                maintenance_mode.0.store(enabled, Ordering::Relaxed);
+                tracing::info!(maintenance_mode = enabled, "Reloaded maintenance mode");
+            }
+            Err(e) => tracing::error!(
+                error.message = %e,
+                "Failed to reload the configuration, keeping the current maintenance mode"
+            ),
+        }
     }
 }
 
 pub fn get_connection_pool(configuration: &DatabaseSettings) -> PgPool {
     PgPoolOptions::new()
+        .max_connections(configuration.max_connections)
+        .test_before_acquire(configuration.test_before_acquire)
         .acquire_timeout(std::time::Duration::from_secs(2))
         .connect_lazy_with(configuration.with_db())
 }
 
+#[allow(clippy::too_many_arguments)]
 pub fn run(
     listener: TcpListener,
     connection_pool: PgPool,
-    email_client: EmailClient,
-    base_url: String,
+    in_flight: web::Data<InFlightRequests>,
+    maintenance_mode: web::Data<MaintenanceMode>,
+    transient_db_retries: u32,
+    email_client: FailoverEmailClient,
+    application: ApplicationSettings,
+    security_headers: SecurityHeadersSettings,
+    webhook: WebhookSettings,
+    confirmation_code: ConfirmationCodeSettings,
+    confirmation_email: ConfirmationEmailSettings,
+    token_cache: TokenCacheSettings,
+    welcome_email: WelcomeEmailSettings,
 ) -> Result<Server, std::io::Error> {
+    let started_at = web::Data::new(StartedAt(std::time::Instant::now()));
     let connection_pool = web::Data::new(connection_pool);
+    let transient_db_retries = web::Data::new(TransientDbRetries(transient_db_retries));
     let email_client = web::Data::new(email_client);
-    let base_url = web::Data::new(ApplicationBaseUrl(base_url));
+    let base_url = web::Data::new(ApplicationBaseUrl(application.base_url));
+    let allowed_redirects = web::Data::new(AllowedRedirects(application.allowed_redirects));
+    let subscriber_metadata_fields = web::Data::new(SubscriberMetadataFields(
+        application.subscriber_metadata_fields,
+    ));
+    let hide_existence = web::Data::new(HideSubscriptionExistence(
+        application.hide_subscription_existence,
+    ));
+    let webhook = web::Data::new(webhook);
+    let confirmation_code = web::Data::new(confirmation_code);
+    let confirmation_email = web::Data::new(confirmation_email);
+    let token_cache = web::Data::new(token_cache.cache());
+    let subscribe_admission = web::Data::new(SubscribeAdmission(Semaphore::new(
+        application
+            .max_concurrent_subscribes
+            .unwrap_or(Semaphore::MAX_PERMITS),
+    )));
+    let welcome_email = web::Data::new(WelcomeEmail(
+        application.send_welcome_email.then_some(welcome_email),
+    ));
+    let csrf_protection = web::Data::new(CsrfProtection(application.csrf_protection));
+    let health_check_path = application.health_check_path;
+    let trailing_slash = application.trailing_slash;
 
     let server = HttpServer::new(move || {
+        let exempt_path = health_check_path.clone();
+        let tracked = in_flight.clone();
         App::new()
+            .wrap_fn(move |req, srv| {
+                let response = if in_maintenance(&req, &exempt_path) {
+                    Err(req.into_response(maintenance_response()))
+                } else {
+                    Ok(srv.call(req))
+                };
+                async move {
+                    match response {
+                        Ok(response) => response.await.map(|r| r.map_into_left_body()),
+                        Err(response) => Ok(response.map_into_right_body()),
+                    }
+                }
+            })
             .wrap(TracingLogger::default())
-            .route("/health_check", web::get().to(health_check))
+            .wrap_fn(move |req, srv| {
+                let guard = InFlightRequests::start(tracked.clone());
+                let response = srv.call(req);
+                async move {
+                    let response = response.await;
+                    guard.finish();
+                    response
+                }
+            })
+            .wrap(default_headers(&security_headers))
+            .wrap(NormalizePath::new(trailing_slash.into()))
+            .route(&health_check_path, web::get().to(health_check))
+            .route("/subscriptions", web::get().to(subscription_form))
             .route("/subscriptions", web::post().to(subscribe))
             .route("/subscriptions/confirm", web::get().to(confirm))
+            .route(
+                "/subscriptions/confirm-code",
+                web::post().to(confirm_with_code),
+            )
+            .route("/version", web::get().to(version))
+            .route("/webhooks/subscribe", web::post().to(subscribe_webhook))
+            .app_data(web::FormConfig::default().error_handler(subscription_form_error_handler))
+            .app_data(started_at.clone())
             .app_data(connection_pool.clone())
+            .app_data(transient_db_retries.clone())
             .app_data(email_client.clone())
             .app_data(base_url.clone())
+            .app_data(allowed_redirects.clone())
+            .app_data(subscriber_metadata_fields.clone())
+            .app_data(hide_existence.clone())
+            .app_data(webhook.clone())
+            .app_data(confirmation_code.clone())
+            .app_data(confirmation_email.clone())
+            .app_data(token_cache.clone())
+            .app_data(welcome_email.clone())
+            .app_data(subscribe_admission.clone())
+            .app_data(maintenance_mode.clone())
+            .app_data(csrf_protection.clone())
     })
+    .disable_signals()
+    .shutdown_timeout(SHUTDOWN_TIMEOUT_SECONDS)
     .listen(listener)?
     .run();
 
     Ok(server)
 }
+
+// The health check stays up so orchestrators do not restart the instance
+fn in_maintenance(req: &ServiceRequest, health_check_path: &str) -> bool {
+    let enabled = req
+        .app_data::<web::Data<MaintenanceMode>>()
+        .map(|mode| mode.0.load(Ordering::Relaxed))
+        .unwrap_or(false);
+    enabled && req.path() != health_check_path
+}
+
+fn maintenance_response() -> HttpResponse {
+    HttpResponse::ServiceUnavailable()
+        .insert_header((RETRY_AFTER, "60"))
+        .content_type(ContentType::html())
+        .body(
+            "<!DOCTYPE html><html><head><title>Down for maintenance</title></head>\
+            <body><p>We are down for maintenance and will be back shortly.</p></body></html>",
+        )
+}
+
+fn default_headers(settings: &SecurityHeadersSettings) -> DefaultHeaders {
+    DefaultHeaders::new()
+        .add((
+            "Content-Security-Policy",
+            settings.content_security_policy.as_str(),
+        ))
+        .add(("X-Content-Type-Options", "nosniff"))
+        .add(("X-Frame-Options", settings.frame_options.as_str()))
+        .add(("Referrer-Policy", settings.referrer_policy.as_str()))
+}
@@ -0,0 +1,112 @@
+use std::collections::HashMap;
+use std::sync::Mutex;
+use std::time::{Duration, Instant};
+use uuid::Uuid;
+
+/// A small bounded cache of subscription token -> subscriber id, so repeated
+/// hits on the same confirmation link do not each query the database.
+pub struct TokenCache {
+    entries: Mutex<HashMap<String, (Uuid, Instant)>>,
+    capacity: usize,
+    ttl: Duration,
+}
+
+impl TokenCache {
+    pub fn new(capacity: usize, ttl: Duration) -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            capacity,
+            ttl,
+        }
+    }
+
+    pub fn get(&self, token: &str) -> Option<Uuid> {
+        let mut entries = self.entries.lock().unwrap();
+        match entries.get(token) {
+            Some((subscriber_id, inserted_at)) if inserted_at.elapsed() < self.ttl => {
+                Some(*subscriber_id)
+            }
+            Some(_) => {
+                entries.remove(token);
+                None
+            }
+            None => None,
+        }
+    }
+
+    pub fn insert(&self, token: &str, subscriber_id: Uuid) {
+        if self.capacity == 0 {
+            return;
+        }
+        let mut entries = self.entries.lock().unwrap();
+        if entries.len() >= self.capacity && !entries.contains_key(token) {
+            let ttl = self.ttl;
+            entries.retain(|_, (_, inserted_at)| inserted_at.elapsed() < ttl);
+        }
+        // Still full of live entries: make room by dropping the oldest one
+        if entries.len() >= self.capacity && !entries.contains_key(token) {
+            let oldest = entries
+                .iter()
+                .min_by_key(|(_, (_, inserted_at))| *inserted_at)
+                .map(|(token, _)| token.clone());
+            if let Some(oldest) = oldest {
+                entries.remove(&oldest);
+            }
+        }
+        entries.insert(token.to_string(), (subscriber_id, Instant::now()));
+    }
+
+    pub fn remove(&self, token: &str) {
+        self.entries.lock().unwrap().remove(token);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use crate::token_cache::TokenCache;
+    use claims::{assert_none, assert_some_eq};
+    use std::time::Duration;
+    use uuid::Uuid;
+
+    #[test]
+    fn a_cached_token_is_returned_within_the_ttl() {
+        let cache = TokenCache::new(10, Duration::from_secs(60));
+        let subscriber_id = Uuid::new_v4();
+
+        cache.insert("token", subscriber_id);
+
+        assert_some_eq!(cache.get("token"), subscriber_id);
+    }
+
+    #[test]
+    fn an_expired_token_is_not_returned() {
+        let cache = TokenCache::new(10, Duration::ZERO);
+
+        cache.insert("token", Uuid::new_v4());
+
+        assert_none!(cache.get("token"));
+    }
+
+    #[test]
+    fn a_removed_token_is_not_returned() {
+        let cache = TokenCache::new(10, Duration::from_secs(60));
+
+        cache.insert("token", Uuid::new_v4());
+        cache.remove("token");
+
+        assert_none!(cache.get("token"));
+    }
+
+    #[test]
+    fn the_oldest_entry_is_evicted_when_full() {
+        let cache = TokenCache::new(2, Duration::from_secs(60));
+
+        cache.insert("first", Uuid::new_v4());
+        cache.insert("second", Uuid::new_v4());
+        cache.insert("third", Uuid::new_v4());
+
+        assert_none!(cache.get("first"));
+        assert!(cache.get("second").is_some());
+        assert!(cache.get("third").is_some());
+    }
+}
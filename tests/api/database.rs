@@ -0,0 +1,78 @@
+use crate::helpers::spawn_app_with;
+use secrecy::{ExposeSecret, Secret};
+use std::sync::{Arc, Mutex};
+use tracing::{Event, Level, Subscriber};
+use tracing_log::NormalizeEvent;
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{Layer, Registry};
+
+// Records the level and target of every event it sees
+#[derive(Clone, Default)]
+struct CapturedEvents(Arc<Mutex<Vec<(Level, String)>>>);
+
+impl<S: Subscriber> Layer<S> for CapturedEvents {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        // Events forwarded from `log` carry their real target in their fields
+        let normalized = event.normalized_metadata();
+        let metadata = normalized.as_ref().unwrap_or_else(|| event.metadata());
+        self.0
+            .lock()
+            .unwrap()
+            .push((*metadata.level(), metadata.target().to_string()));
+    }
+}
+
+#[tokio::test]
+async fn slow_queries_are_logged_as_warnings() {
+    let app = spawn_app_with(|c| c.database.slow_query_threshold_ms = 10).await;
+    let events = CapturedEvents::default();
+    // sqlx logs through `log`, which the test logger forwards to this thread's subscriber
+    let _guard = tracing::subscriber::set_default(Registry::default().with(events.clone()));
+
+    sqlx::query("SELECT pg_sleep(0.05)")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let events = events.0.lock().unwrap();
+    assert!(events
+        .iter()
+        .any(|(level, target)| *level == Level::WARN && target == "sqlx::query"));
+}
+
+#[tokio::test]
+async fn connections_carry_the_configured_application_name() {
+    let app = spawn_app_with(|c| c.database.application_name = "newsletter-api".into()).await;
+
+    let (application_name,): (String,) =
+        sqlx::query_as("SELECT current_setting('application_name')")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(application_name, "newsletter-api");
+}
+
+#[tokio::test]
+async fn connections_from_a_database_url_carry_the_configured_application_name() {
+    let app = spawn_app_with(|c| {
+        c.database.application_name = "newsletter-api".into();
+        c.database.url = Some(Secret::new(format!(
+            "postgres://{}:{}@{}:{}/{}",
+            c.database.username,
+            c.database.password.expose_secret(),
+            c.database.host,
+            c.database.port,
+            c.database.database_name
+        )));
+    })
+    .await;
+
+    let (application_name,): (String,) =
+        sqlx::query_as("SELECT current_setting('application_name')")
+            .fetch_one(&app.db_pool)
+            .await
+            .unwrap();
+
+    assert_eq!(application_name, "newsletter-api");
+}
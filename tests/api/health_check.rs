@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 
 #[tokio::test]
 async fn health_check_works() {
@@ -6,7 +6,7 @@ async fn health_check_works() {
     let client = reqwest::Client::new();
 
     let response = client
-        .get(&format!("{}/health_check", &app.address))
+        .get(format!("{}/health_check", &app.address))
         .send()
         .await
         .expect("Failed to execute request");
@@ -14,3 +14,79 @@ async fn health_check_works() {
     assert!(response.status().is_success());
     assert_eq!(Some(0), response.content_length());
 }
+
+#[tokio::test]
+async fn health_check_is_served_at_the_configured_path() {
+    let app = spawn_app_with(|c| c.application.health_check_path = "/healthz".into()).await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/healthz", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert!(response.status().is_success());
+
+    let response = client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(404, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn responses_carry_the_configured_security_headers() {
+    let app = spawn_app_with(|c| {
+        c.security_headers.content_security_policy = "default-src 'none'".into();
+    })
+    .await;
+
+    let response = reqwest::get(format!("{}/health_check", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    let headers = response.headers();
+    assert_eq!(headers["Content-Security-Policy"], "default-src 'none'");
+    assert_eq!(headers["X-Content-Type-Options"], "nosniff");
+    assert_eq!(headers["X-Frame-Options"], "DENY");
+    assert_eq!(headers["Referrer-Policy"], "no-referrer");
+}
+
+#[tokio::test]
+async fn health_check_returns_json_when_asked_for_it() {
+    let app = spawn_app().await;
+    let client = reqwest::Client::new();
+
+    let response = client
+        .get(format!("{}/health_check", &app.address))
+        .header("Accept", "application/json")
+        .send()
+        .await
+        .expect("Failed to execute request");
+
+    assert!(response.status().is_success());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["status"], "ok");
+    assert!(body["uptime_seconds"].is_u64());
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+}
+
+#[tokio::test]
+async fn only_the_health_check_is_served_in_maintenance_mode() {
+    let app = spawn_app_with(|c| c.application.maintenance_mode = true).await;
+    let client = reqwest::Client::new();
+
+    let response = app
+        .post_subscriptions("name=le%20guin&email=ursula_le_guin%40gmail.com".into())
+        .await;
+    assert_eq!(503, response.status().as_u16());
+    assert!(response.headers().contains_key("Retry-After"));
+
+    let response = client
+        .get(format!("{}/health_check", &app.address))
+        .send()
+        .await
+        .expect("Failed to execute request");
+    assert_eq!(200, response.status().as_u16());
+}
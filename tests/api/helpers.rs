@@ -1,5 +1,5 @@
 use email_newsletter::{
-    configuration::{get_configuration, DatabaseSettings},
+    configuration::{get_configuration, DatabaseSettings, Settings},
     startup::{get_connection_pool, Application},
     telemetry::{get_subscriber, init_subscriber},
 };
@@ -35,6 +35,14 @@ pub struct ConfirmationLinks {
 }
 
 pub async fn spawn_app() -> TestApp {
+    spawn_app_with(|_| {}).await
+}
+
+// Spawn the application after letting the test tweak its configuration
+pub async fn spawn_app_with<F>(customise: F) -> TestApp
+where
+    F: FnOnce(&mut Settings),
+{
     Lazy::force(&TRACING);
     let email_server = MockServer::start().await;
 
@@ -42,9 +50,12 @@ pub async fn spawn_app() -> TestApp {
         let mut c = get_configuration().expect("Faild to read configuration");
         // Use a different database for each test case
         c.database.database_name = Uuid::new_v4().to_string();
+        // A DATABASE_URL exported for sqlx would name a shared database
+        c.database.url = None;
         // Use a random OS port
         c.application.port = 0;
         c.email_client.base_url = email_server.uri();
+        customise(&mut c);
         c
     };
 
@@ -56,7 +67,7 @@ pub async fn spawn_app() -> TestApp {
     // Get the port before spawning the application
     let port = application.port();
 
-    let _ = tokio::spawn(application.run_until_stopped());
+    drop(tokio::spawn(application.run_until_stopped()));
 
     TestApp {
         address: format!("http://localhost:{}", port),
@@ -91,14 +102,101 @@ async fn configure_database(config: &DatabaseSettings) -> PgPool {
 impl TestApp {
     pub async fn post_subscriptions(&self, body: String) -> reqwest::Response {
         reqwest::Client::new()
-            .post(&format!("{}/subscriptions", &self.address))
+            .post(format!("{}/subscriptions", &self.address))
+            .header("Content-Type", "application/x-www-form-urlencoded")
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Returns the CSRF token set in the cookie and echoed in the form
+    pub async fn get_subscription_form(&self) -> (String, String) {
+        let response = reqwest::Client::new()
+            .get(format!("{}/subscriptions", &self.address))
+            .send()
+            .await
+            .expect("Failed to execute request");
+        let cookie = response
+            .headers()
+            .get_all("Set-Cookie")
+            .iter()
+            .filter_map(|value| value.to_str().ok())
+            .find_map(|value| value.strip_prefix("csrf_token="))
+            .and_then(|value| value.split(';').next())
+            .expect("No CSRF cookie was set")
+            .to_owned();
+        let html = response.text().await.unwrap();
+        let field = html
+            .split(r#"name="csrf_token" value=""#)
+            .nth(1)
+            .and_then(|rest| rest.split('"').next())
+            .expect("No CSRF field in the form")
+            .to_owned();
+        (cookie, field)
+    }
+
+    // Posts the way a browser would, with an Origin header and the CSRF cookie
+    pub async fn post_subscriptions_from_browser(
+        &self,
+        body: String,
+        csrf_cookie: &str,
+    ) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions", &self.address))
             .header("Content-Type", "application/x-www-form-urlencoded")
+            .header("Origin", &self.address)
+            .header("Cookie", format!("csrf_token={}", csrf_cookie))
             .body(body)
             .send()
             .await
             .expect("Failed to execute request")
     }
 
+    pub async fn post_subscribe_webhook(
+        &self,
+        body: String,
+        signature: Option<String>,
+    ) -> reqwest::Response {
+        let mut request = reqwest::Client::new()
+            .post(format!("{}/webhooks/subscribe", &self.address))
+            .header("Content-Type", "application/json");
+        if let Some(signature) = signature {
+            request = request.header("X-Signature", signature);
+        }
+        request
+            .body(body)
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    pub async fn post_confirm_code(&self, email: &str, code: &str) -> reqwest::Response {
+        reqwest::Client::new()
+            .post(format!("{}/subscriptions/confirm-code", &self.address))
+            .json(&serde_json::json!({ "email": email, "code": code }))
+            .send()
+            .await
+            .expect("Failed to execute request")
+    }
+
+    // Follows the link in the first email the provider received
+    pub async fn confirm_first_subscription(&self) {
+        let email_request = &self.email_server.received_requests().await.unwrap()[0];
+        let confirmation_links = self.get_confirmation_links(email_request);
+        let response = reqwest::get(confirmation_links.html).await.unwrap();
+        assert_eq!(200, response.status().as_u16());
+    }
+
+    pub fn get_confirmation_code(&self, email_request: &wiremock::Request) -> String {
+        let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
+        let text = body["Messages"][0]["TextPart"].as_str().unwrap();
+        let (_, code) = text
+            .split_once("confirmation code: ")
+            .expect("No confirmation code in the email");
+        code.chars().take(6).collect()
+    }
+
     pub fn get_confirmation_links(&self, email_request: &wiremock::Request) -> ConfirmationLinks {
         let body: serde_json::Value = serde_json::from_slice(&email_request.body).unwrap();
 
@@ -116,8 +214,8 @@ impl TestApp {
         };
 
         let body_part = &body["Messages"][0];
-        let html = get_link(&body_part["HTMLPart"].as_str().unwrap());
-        let plain_text = get_link(&body_part["TextPart"].as_str().unwrap());
+        let html = get_link(body_part["HTMLPart"].as_str().unwrap());
+        let plain_text = get_link(body_part["TextPart"].as_str().unwrap());
 
         ConfirmationLinks { html, plain_text }
     }
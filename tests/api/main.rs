@@ -1,4 +1,9 @@
+mod database;
 mod health_check;
 mod helpers;
+mod startup;
 mod subscriptions;
 mod subscriptions_confirm;
+mod subscriptions_confirm_code;
+mod version;
+mod webhooks;
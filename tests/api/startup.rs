@@ -0,0 +1,74 @@
+use crate::helpers::spawn_app_with;
+use secrecy::Secret;
+use std::collections::HashMap;
+use std::fmt::Debug;
+use std::sync::{Arc, Mutex};
+use tracing::field::{Field, Visit};
+use tracing::{Event, Subscriber};
+use tracing_subscriber::layer::{Context, SubscriberExt};
+use tracing_subscriber::{Layer, Registry};
+
+const API_TOKEN: &str = "startup-log-api-token";
+const SECRET_TOKEN: &str = "startup-log-secret-token";
+const WEBHOOK_SECRET: &str = "startup-log-webhook-secret";
+const PROXY_PASSWORD: &str = "startup-log-proxy-password";
+
+// Records the fields of every event it sees, keyed by field name
+#[derive(Clone, Default)]
+struct CapturedFields(Arc<Mutex<Vec<HashMap<String, String>>>>);
+
+impl<S: Subscriber> Layer<S> for CapturedFields {
+    fn on_event(&self, event: &Event<'_>, _ctx: Context<'_, S>) {
+        let mut fields = FieldRecorder::default();
+        event.record(&mut fields);
+        self.0.lock().unwrap().push(fields.0);
+    }
+}
+
+#[derive(Default)]
+struct FieldRecorder(HashMap<String, String>);
+
+impl Visit for FieldRecorder {
+    fn record_debug(&mut self, field: &Field, value: &dyn Debug) {
+        self.0.insert(field.name().into(), format!("{:?}", value));
+    }
+
+    fn record_str(&mut self, field: &Field, value: &str) {
+        self.0.insert(field.name().into(), value.into());
+    }
+}
+
+#[tokio::test]
+async fn the_startup_log_shows_the_bind_address_without_secrets() {
+    let events = CapturedFields::default();
+    // The application is built on this thread, so its startup event lands here
+    let _guard = tracing::subscriber::set_default(Registry::default().with(events.clone()));
+
+    let _app = spawn_app_with(|c| {
+        c.email_client.api_token = Secret::new(API_TOKEN.into());
+        c.email_client.secret_token = Secret::new(SECRET_TOKEN.into());
+        c.webhook.secret = Some(Secret::new(WEBHOOK_SECRET.into()));
+        c.email_client.proxy_url = Some(Secret::new(format!(
+            "http://newsletter:{}@proxy.internal:3128",
+            PROXY_PASSWORD
+        )));
+    })
+    .await;
+
+    let events = events.0.lock().unwrap();
+    let startup = events
+        .iter()
+        .find(|fields| fields.contains_key("configuration"))
+        .expect("The startup configuration was not logged");
+    assert_eq!(startup["host"], "127.0.0.1");
+    assert_eq!(startup["port"], "0");
+    for value in startup.values() {
+        for secret in [API_TOKEN, SECRET_TOKEN, WEBHOOK_SECRET, PROXY_PASSWORD] {
+            assert!(
+                !value.contains(secret),
+                "{} leaked into the startup log",
+                secret
+            );
+        }
+    }
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -140,3 +140,551 @@ async fn subscribe_fails_if_there_is_a_fatal_database_error() {
     // Assert
     assert_eq!(response.status().as_u16(), 500);
 }
+
+#[tokio::test]
+async fn subscribe_rejection_does_not_echo_the_submitted_values() {
+    let app = spawn_app().await;
+    let test_cases = vec![
+        (
+            "name=mr%20t&email=definitely-not-an-email",
+            "definitely-not-an-email",
+        ),
+        ("name=%3Cscript%3E&email=mr_t%40test.com", "<script>"),
+    ];
+
+    for (body, value) in test_cases {
+        let response = app.post_subscriptions(body.into()).await;
+
+        assert_eq!(400, response.status().as_u16());
+        let text = response.text().await.unwrap();
+        assert!(!text.contains(value));
+    }
+}
+
+#[tokio::test]
+async fn subscribe_rejection_names_the_invalid_email_field() {
+    let app = spawn_app().await;
+    let body = "name=mr%20t&email=definitely-not-an-email";
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+    let text = response.text().await.unwrap();
+    assert!(text.contains("email"));
+    assert!(!text.contains("definitely-not-an-email"));
+}
+
+#[tokio::test]
+async fn subscribe_stores_allowlisted_extra_fields_as_metadata() {
+    let app = spawn_app_with(|c| {
+        c.application.subscriber_metadata_fields = vec!["company".into()];
+    })
+    .await;
+    let body = "name=mr%20test&email=mr_t%40test.com&company=Acme&referrer=somewhere";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT metadata FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.metadata, serde_json::json!({ "company": "Acme" }));
+}
+
+#[tokio::test]
+async fn subscribing_twice_returns_a_409_by_default() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    app.confirm_first_subscription().await;
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(409, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribing_twice_looks_like_a_new_subscription_when_existence_is_hidden() {
+    let app = spawn_app_with(|c| c.application.hide_subscription_existence = true).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    // The confirmation, then a notice standing in for a second confirmation
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    app.confirm_first_subscription().await;
+    let second = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(first.status(), second.status());
+    assert_eq!(first.text().await.unwrap(), second.text().await.unwrap());
+}
+
+#[tokio::test]
+async fn subscribing_again_while_pending_resends_the_confirmation_email() {
+    let app = spawn_app_with(|c| c.confirmation_email.retries = 0).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    // Mocks are matched in mount order, so the first send fails
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    let second = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+    // The new link confirms the subscription
+    let email_request = &app.email_server.received_requests().await.unwrap()[1];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn subscribe_retries_a_serialization_failure() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+    // Fails the first insert with 40001. Sequences are not rolled back, so
+    // the rerun transaction gets through.
+    sqlx::query("CREATE SEQUENCE subscribe_attempts")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+    sqlx::query(
+        r#"
+        CREATE FUNCTION fail_first_subscribe() RETURNS trigger AS $$
+        BEGIN
+            IF nextval('subscribe_attempts') = 1 THEN
+                RAISE EXCEPTION 'simulated serialization failure' USING ERRCODE = '40001';
+            END IF;
+            RETURN NEW;
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "CREATE TRIGGER fail_first_subscribe BEFORE INSERT ON subscriptions \
+        FOR EACH ROW EXECUTE FUNCTION fail_first_subscribe()",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(saved.email, "mr_t@test.com");
+}
+
+#[tokio::test]
+async fn a_failed_confirmation_email_is_retried_within_the_request() {
+    let app = spawn_app_with(|c| c.confirmation_email.retry_delay_milliseconds = 10).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    // Mocks are matched in mount order, so the 500 is served first
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_retried_confirmation_email_keeps_its_custom_id() {
+    let app = spawn_app_with(|c| c.confirmation_email.retry_delay_milliseconds = 10).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .up_to_n_times(1)
+        .mount(&app.email_server)
+        .await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let custom_ids: Vec<_> = app
+        .email_server
+        .received_requests()
+        .await
+        .unwrap()
+        .iter()
+        .map(|request| {
+            let body: serde_json::Value = serde_json::from_slice(&request.body).unwrap();
+            body["Messages"][0]["CustomID"].as_str().unwrap().to_owned()
+        })
+        .collect();
+    assert_eq!(custom_ids.len(), 2);
+    assert_eq!(custom_ids[0], custom_ids[1]);
+}
+
+#[tokio::test]
+async fn subscribe_fails_once_confirmation_email_retries_are_exhausted() {
+    let app = spawn_app_with(|c| {
+        c.confirmation_email.retries = 2;
+        c.confirmation_email.retry_delay_milliseconds = 10;
+    })
+    .await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .expect(3)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_confirmation_email_rejected_by_the_provider_is_not_retried() {
+    let app = spawn_app_with(|c| c.confirmation_email.retry_delay_milliseconds = 10).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(400))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn subscribe_returns_a_503_with_retry_after_when_saturated() {
+    // No permits at all, as if every one was held by an in-flight request
+    let app = spawn_app_with(|c| c.application.max_concurrent_subscribes = Some(0)).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(503, response.status().as_u16());
+    assert!(response.headers().contains_key("Retry-After"));
+}
+
+#[tokio::test]
+async fn a_double_submitted_form_is_only_processed_once() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=6f1c3b52-8d7e-4c6a-9a51-2f0d1b7e9c44";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app.post_subscriptions(body.into()).await;
+    let second = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, first.status().as_u16());
+    // Replayed rather than reported as an already existing subscription
+    assert_eq!(200, second.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_concurrent_double_submission_is_only_processed_once() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=0b8e2f4a-53c1-4d7e-8f26-9a1c7e3d5b60";
+
+    // Slow enough that the second submission arrives while the first is running
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200).set_delay(std::time::Duration::from_millis(300)))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (first, second) = tokio::join!(
+        app.post_subscriptions(body.into()),
+        app.post_subscriptions(body.into())
+    );
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(200, second.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_failed_submission_releases_its_idempotency_key() {
+    let app = spawn_app_with(|c| c.confirmation_email.retries = 0).await;
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=5d2a7c1e-9b34-4f80-a6e2-1c8d3b7f0e95";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(500))
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(500, response.status().as_u16());
+    // Nothing is left behind, so a retry with the same key is processed again
+    let saved = sqlx::query!("SELECT idempotency_key FROM subscribe_idempotency")
+        .fetch_optional(&app.db_pool)
+        .await
+        .unwrap();
+    assert!(saved.is_none());
+}
+
+#[tokio::test]
+async fn expired_idempotency_keys_are_deleted() {
+    let app = spawn_app().await;
+    sqlx::query!(
+        r#"
+        INSERT INTO subscribe_idempotency
+            (idempotency_key, request_hash, response_status_code, created_at)
+        VALUES ($1, '', 200, now() - interval '2 days')
+        "#,
+        uuid::Uuid::new_v4()
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=7e4b9d2c-1a65-4c3f-b8d0-3f2e6a9c1b47";
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+
+    let remaining = sqlx::query!("SELECT idempotency_key FROM subscribe_idempotency")
+        .fetch_all(&app.db_pool)
+        .await
+        .unwrap();
+    assert_eq!(remaining.len(), 1);
+}
+
+#[tokio::test]
+async fn an_idempotency_key_reused_for_a_different_form_is_rejected() {
+    let app = spawn_app().await;
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let first = app
+        .post_subscriptions(
+            "name=mr%20test&email=mr_t%40test.com\
+            &idempotency_key=2c9e6b1d-7f43-4a58-b0d2-8e1f5a3c7d96"
+                .into(),
+        )
+        .await;
+    let second = app
+        .post_subscriptions(
+            "name=ms%20test&email=ms_t%40test.com\
+            &idempotency_key=2c9e6b1d-7f43-4a58-b0d2-8e1f5a3c7d96"
+                .into(),
+        )
+        .await;
+
+    assert_eq!(200, first.status().as_u16());
+    assert_eq!(422, second.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_abandoned_idempotency_claim_is_taken_over() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=9a3f5c7e-2b86-4d19-a4e0-6c1b8d2f7e53";
+
+    // The first submission, then the one taking over the abandoned claim
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    // As if the process died before saving the response
+    sqlx::query!(
+        r#"
+        UPDATE subscribe_idempotency
+        SET response_status_code = NULL, created_at = now() - interval '5 minutes'
+        "#
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_subscription_succeeds_even_if_its_response_cannot_be_saved() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com\
+        &idempotency_key=4e8b2d6f-1c37-4a95-b7e1-0d5f9c3a2b68";
+    sqlx::query(
+        r#"
+        CREATE FUNCTION reject_update() RETURNS trigger AS $$
+        BEGIN
+            RAISE EXCEPTION 'simulated failure';
+        END;
+        $$ LANGUAGE plpgsql
+        "#,
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+    sqlx::query(
+        "CREATE TRIGGER reject_update BEFORE UPDATE ON subscribe_idempotency \
+        FOR EACH ROW EXECUTE FUNCTION reject_update()",
+    )
+    .execute(&app.db_pool)
+    .await
+    .unwrap();
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn an_invalid_idempotency_key_is_rejected() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com&idempotency_key=not-a-uuid";
+
+    let response = app.post_subscriptions(body.into()).await;
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_browser_form_post_with_the_csrf_token_subscribes() {
+    let app = spawn_app_with(|c| c.application.csrf_protection = true).await;
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let (cookie, field) = app.get_subscription_form().await;
+    assert_eq!(cookie, field);
+    let body = format!(
+        "name=le%20guin&email=ursula_le_guin%40gmail.com&csrf_token={}",
+        field
+    );
+    let response = app.post_subscriptions_from_browser(body, &cookie).await;
+
+    assert_eq!(200, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_browser_form_post_without_the_csrf_token_is_forbidden() {
+    let app = spawn_app_with(|c| c.application.csrf_protection = true).await;
+
+    let (cookie, _) = app.get_subscription_form().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com".to_string();
+    let response = app.post_subscriptions_from_browser(body, &cookie).await;
+
+    assert_eq!(403, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_browser_form_post_with_a_mismatched_csrf_token_is_forbidden() {
+    let app = spawn_app_with(|c| c.application.csrf_protection = true).await;
+
+    let (cookie, _) = app.get_subscription_form().await;
+    let body = "name=le%20guin&email=ursula_le_guin%40gmail.com&csrf_token=forged".to_string();
+    let response = app.post_subscriptions_from_browser(body, &cookie).await;
+
+    assert_eq!(403, response.status().as_u16());
+}
@@ -1,4 +1,4 @@
-use crate::helpers::spawn_app;
+use crate::helpers::{spawn_app, spawn_app_with};
 use wiremock::matchers::{method, path};
 use wiremock::{Mock, ResponseTemplate};
 
@@ -127,3 +127,248 @@ async fn clicking_twice_on_the_confirmation_link_returns_401() {
 
     assert!(second_request.is_err());
 }
+
+#[tokio::test]
+async fn confirmation_redirects_to_an_allowed_target() {
+    let app = spawn_app_with(|c| {
+        c.application.allowed_redirects = vec!["https://example.com/thank-you".into()]
+    })
+    .await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let mut confirmation_link = app.get_confirmation_links(email_request).html;
+    confirmation_link
+        .query_pairs_mut()
+        .append_pair("redirect_to", "https://example.com/thank-you");
+
+    // Act
+    let response = reqwest::Client::builder()
+        .redirect(reqwest::redirect::Policy::none())
+        .build()
+        .unwrap()
+        .get(confirmation_link)
+        .send()
+        .await
+        .unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 303);
+    assert_eq!(
+        response.headers().get("Location").unwrap(),
+        "https://example.com/thank-you"
+    );
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn confirmation_rejects_a_redirect_target_outside_the_allowlist() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let mut confirmation_link = app.get_confirmation_links(email_request).html;
+    confirmation_link
+        .query_pairs_mut()
+        .append_pair("redirect_to", "https://evil.example.com");
+
+    // Act
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 400);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn confirmation_fails_if_checking_the_subscriber_status_errors() {
+    // Arrange
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // Sabotage the database
+    sqlx::query!("ALTER TABLE subscriptions DROP COLUMN status;")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    // Act
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+
+    // Assert
+    assert_eq!(response.status().as_u16(), 500);
+}
+
+#[tokio::test]
+async fn a_confirmation_link_with_a_trailing_slash_still_confirms() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let mut confirmation_link = app.get_confirmation_links(email_request).html;
+    confirmation_link.set_path("/subscriptions/confirm/");
+
+    let response = reqwest::get(confirmation_link).await.unwrap();
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription.");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn a_welcome_email_follows_confirmation_when_enabled() {
+    let app = spawn_app_with(|c| {
+        c.application.send_welcome_email = true;
+        c.welcome_email.subject = "Welcome aboard, {{name}}!".into();
+    })
+    .await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    let response = reqwest::get(confirmation_links.html).await.unwrap();
+    assert_eq!(response.status().as_u16(), 200);
+
+    // The welcome email is sent in the background, give it a moment
+    let mut requests = vec![];
+    for _ in 0..50 {
+        requests = app.email_server.received_requests().await.unwrap();
+        if requests.len() == 2 {
+            break;
+        }
+        tokio::time::sleep(std::time::Duration::from_millis(20)).await;
+    }
+    let welcome: serde_json::Value = serde_json::from_slice(&requests[1].body).unwrap();
+    assert_eq!(
+        welcome["Messages"][0]["Subject"],
+        "Welcome aboard, mr test!"
+    );
+    assert_eq!(welcome["Messages"][0]["To"][0]["Email"], "mr_t@test.com");
+}
+
+#[tokio::test]
+async fn concurrent_confirmations_send_a_single_welcome_email() {
+    let app = spawn_app_with(|c| c.application.send_welcome_email = true).await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    // The confirmation and exactly one welcome email
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(2)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    // A mail scanner prefetching the link while the user clicks it
+    let (scanner, user) = tokio::join!(
+        reqwest::get(confirmation_links.html.clone()),
+        reqwest::get(confirmation_links.html)
+    );
+    let statuses = [scanner.unwrap().status(), user.unwrap().status()];
+    assert!(statuses.iter().any(|status| status.is_success()));
+
+    // Give a second, wrongly spawned welcome email time to arrive
+    tokio::time::sleep(std::time::Duration::from_millis(300)).await;
+}
+
+#[tokio::test]
+async fn no_welcome_email_is_sent_by_default() {
+    let app = spawn_app().await;
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    let confirmation_links = app.get_confirmation_links(email_request);
+    reqwest::get(confirmation_links.html)
+        .await
+        .unwrap()
+        .error_for_status()
+        .unwrap();
+}
+
+#[tokio::test]
+async fn a_malformed_token_is_rejected_with_a_400() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=not-a-token",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(400, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_well_formed_unknown_token_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(&format!(
+        "{}/subscriptions/confirm?subscription_token=abcdefghijklmnopqrstuvw12",
+        app.address
+    ))
+    .await
+    .unwrap();
+
+    assert_eq!(401, response.status().as_u16());
+}
@@ -0,0 +1,157 @@
+use crate::helpers::{spawn_app, TestApp};
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+async fn subscribe_and_get_code(app: &TestApp) -> String {
+    let body = "name=mr%20test&email=mr_t%40test.com";
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .mount(&app.email_server)
+        .await;
+
+    app.post_subscriptions(body.into()).await;
+    let email_request = &app.email_server.received_requests().await.unwrap()[0];
+    app.get_confirmation_code(email_request)
+}
+
+fn wrong_code(code: &str) -> String {
+    if code == "000000" {
+        "000001".into()
+    } else {
+        "000000".into()
+    }
+}
+
+#[tokio::test]
+async fn the_emailed_code_confirms_a_subscriber() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    let response = app.post_confirm_code("mr_t@test.com", &code).await;
+
+    assert_eq!(response.status().as_u16(), 200);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "confirmed");
+}
+
+#[tokio::test]
+async fn the_code_is_not_stored_in_plaintext() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    let saved = sqlx::query!("SELECT code_hash FROM confirmation_codes")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved confirmation code");
+    assert!(!saved.code_hash.contains(&code));
+}
+
+#[tokio::test]
+async fn a_wrong_code_is_rejected_with_a_401() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    let response = app
+        .post_confirm_code("mr_t@test.com", &wrong_code(&code))
+        .await;
+
+    assert_eq!(response.status().as_u16(), 401);
+    let saved = sqlx::query!("SELECT status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscriptions");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn verification_is_locked_after_too_many_wrong_codes() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    for _ in 0..5 {
+        let response = app
+            .post_confirm_code("mr_t@test.com", &wrong_code(&code))
+            .await;
+        assert_eq!(response.status().as_u16(), 401);
+    }
+
+    // Even the right code is refused once locked
+    let response = app.post_confirm_code("mr_t@test.com", &code).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn parallel_wrong_codes_cannot_exceed_the_attempt_limit() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    // Fired at once, so every request reads the counter before any increments it
+    let guesses: Vec<_> = (0..10)
+        .map(|_| {
+            let url = format!("{}/subscriptions/confirm-code", &app.address);
+            let body = serde_json::json!({ "email": "mr_t@test.com", "code": wrong_code(&code) });
+            tokio::spawn(async move {
+                reqwest::Client::new()
+                    .post(url)
+                    .json(&body)
+                    .send()
+                    .await
+                    .expect("Failed to execute request")
+                    .status()
+                    .as_u16()
+            })
+        })
+        .collect();
+    let mut rejected = 0;
+    for guess in guesses {
+        if guess.await.unwrap() == 401 {
+            rejected += 1;
+        }
+    }
+
+    assert_eq!(rejected, 5);
+    let response = app.post_confirm_code("mr_t@test.com", &code).await;
+    assert_eq!(response.status().as_u16(), 429);
+}
+
+#[tokio::test]
+async fn an_expired_code_is_rejected() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+    sqlx::query!("UPDATE confirmation_codes SET expires_at = now() - interval '1 minute'")
+        .execute(&app.db_pool)
+        .await
+        .unwrap();
+
+    let response = app.post_confirm_code("mr_t@test.com", &code).await;
+
+    assert_eq!(response.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn a_code_cannot_be_used_twice() {
+    let app = spawn_app().await;
+    let code = subscribe_and_get_code(&app).await;
+
+    let first = app.post_confirm_code("mr_t@test.com", &code).await;
+    let second = app.post_confirm_code("mr_t@test.com", &code).await;
+
+    assert_eq!(first.status().as_u16(), 200);
+    assert_eq!(second.status().as_u16(), 401);
+}
+
+#[tokio::test]
+async fn an_invalid_email_is_rejected_without_echoing_it() {
+    let app = spawn_app().await;
+
+    let response = app.post_confirm_code("mr_t-at-test.com", "123456").await;
+
+    assert_eq!(response.status().as_u16(), 400);
+    let body = response.text().await.unwrap();
+    assert!(!body.contains("mr_t-at-test.com"), "{}", body);
+}
@@ -0,0 +1,16 @@
+use crate::helpers::spawn_app;
+
+#[tokio::test]
+async fn version_returns_the_crate_version() {
+    let app = spawn_app().await;
+
+    let response = reqwest::get(format!("{}/version", &app.address))
+        .await
+        .expect("Failed to execute request");
+
+    assert_eq!(200, response.status().as_u16());
+    let body: serde_json::Value = response.json().await.unwrap();
+    assert_eq!(body["version"], env!("CARGO_PKG_VERSION"));
+    assert!(body["git_sha"].is_string());
+    assert!(body["build_timestamp"].is_string());
+}
@@ -0,0 +1,101 @@
+use crate::helpers::{spawn_app, spawn_app_with, TestApp};
+use hmac::{Hmac, Mac};
+use secrecy::Secret;
+use sha2::Sha256;
+use wiremock::matchers::{method, path};
+use wiremock::{Mock, ResponseTemplate};
+
+const WEBHOOK_SECRET: &str = "webhook-secret";
+
+async fn spawn_app_with_webhook_secret() -> TestApp {
+    spawn_app_with(|c| c.webhook.secret = Some(Secret::new(WEBHOOK_SECRET.into()))).await
+}
+
+fn sign(body: &str, secret: &str) -> String {
+    let mut mac = Hmac::<Sha256>::new_from_slice(secret.as_bytes()).unwrap();
+    mac.update(body.as_bytes());
+    hex::encode(mac.finalize().into_bytes())
+}
+
+fn payload() -> String {
+    serde_json::json!({ "name": "mr test", "email": "mr_t@test.com" }).to_string()
+}
+
+#[tokio::test]
+async fn a_signed_webhook_creates_a_pending_subscriber() {
+    let app = spawn_app_with_webhook_secret().await;
+    let body = payload();
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(1)
+        .mount(&app.email_server)
+        .await;
+
+    let response = app
+        .post_subscribe_webhook(body.clone(), Some(sign(&body, WEBHOOK_SECRET)))
+        .await;
+
+    assert_eq!(200, response.status().as_u16());
+    let saved = sqlx::query!("SELECT email, name, status FROM subscriptions")
+        .fetch_one(&app.db_pool)
+        .await
+        .expect("Failed to fetch saved subscription");
+    assert_eq!(saved.email, "mr_t@test.com");
+    assert_eq!(saved.name, "mr test");
+    assert_eq!(saved.status, "pending_confirmation");
+}
+
+#[tokio::test]
+async fn webhooks_without_a_valid_signature_are_rejected() {
+    let app = spawn_app_with_webhook_secret().await;
+    let body = payload();
+
+    Mock::given(path("/send"))
+        .and(method("POST"))
+        .respond_with(ResponseTemplate::new(200))
+        .expect(0)
+        .mount(&app.email_server)
+        .await;
+
+    let test_cases = vec![
+        (None, "missing signature"),
+        (Some(sign(&body, "another-secret")), "wrong secret"),
+        (Some("not-hex".to_string()), "malformed signature"),
+    ];
+
+    for (signature, description) in test_cases {
+        let response = app.post_subscribe_webhook(body.clone(), signature).await;
+        assert_eq!(
+            401,
+            response.status().as_u16(),
+            "The API did not reject the webhook with a {}.",
+            description
+        );
+    }
+}
+
+#[tokio::test]
+async fn webhooks_are_rejected_when_no_secret_is_configured() {
+    let app = spawn_app().await;
+    let body = payload();
+
+    let response = app
+        .post_subscribe_webhook(body.clone(), Some(sign(&body, "")))
+        .await;
+
+    assert_eq!(401, response.status().as_u16());
+}
+
+#[tokio::test]
+async fn a_signed_webhook_with_an_invalid_payload_is_a_400() {
+    let app = spawn_app_with_webhook_secret().await;
+    let body = serde_json::json!({ "name": "mr test", "email": "not-an-email" }).to_string();
+
+    let response = app
+        .post_subscribe_webhook(body.clone(), Some(sign(&body, WEBHOOK_SECRET)))
+        .await;
+
+    assert_eq!(400, response.status().as_u16());
+}